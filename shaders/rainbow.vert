@@ -1,7 +1,11 @@
 #version 450
 
 layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
 layout(location = 1) out vec4 vertex_color;
+layout(push_constant) uniform View {
+    mat4 view_projection;
+} view;
 
 vec4 colors[3] = vec4[3](
     vec4(1.0, 0.0, 0.0, 1.0),
@@ -10,6 +14,6 @@ vec4 colors[3] = vec4[3](
 );
 
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
-    vertex_color = colors[gl_VertexIndex];
+    gl_Position = view.view_projection * vec4(position, 0.0, 1.0);
+    vertex_color = colors[gl_VertexIndex % 3] * color;
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
 #version 450
 
-layout(location = 0) in vec3 in_color;
+layout(location = 0) in vec4 in_color;
 layout(location = 0) out vec4 f_color;
 
 void main() {
-    f_color = vec4(in_color, 1.0);
+    f_color = in_color;
 }
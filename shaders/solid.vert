@@ -1,10 +1,13 @@
 #version 450
 
 layout(location = 0) in vec2 position;
-layout(location = 1) in vec3 color;
-layout(location = 0) out vec3 out_color;
+layout(location = 1) in vec4 color;
+layout(location = 0) out vec4 out_color;
+layout(push_constant) uniform View {
+    mat4 view_projection;
+} view;
 
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
+    gl_Position = view.view_projection * vec4(position, 0.0, 1.0);
     out_color = color;
 }
\ No newline at end of file
@@ -2,9 +2,15 @@
 
 layout(location = 0) in vec2 position;
 layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color;
 layout(location = 0) out vec2 tex_coords;
+layout(location = 1) out vec4 tint;
+layout(push_constant) uniform View {
+    mat4 view_projection;
+} view;
 
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
+    gl_Position = view.view_projection * vec4(position, 0.0, 1.0);
     tex_coords = uv;
+    tint = color;
 }
\ No newline at end of file
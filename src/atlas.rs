@@ -0,0 +1,444 @@
+use crate::capture::Image;
+use crate::texture::{Texture, TextureError};
+use crate::xml::write_element;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A rectangle of a texture in pixels, from its top left corner
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Whether the region is at least a pixel in size and lies within an
+    /// image of the given size
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        let right = self.x.checked_add(self.width);
+        let bottom = self.y.checked_add(self.height);
+
+        self.width > 0 && self.height > 0
+            && right.is_some_and(|r| r <= width)
+            && bottom.is_some_and(|b| b <= height)
+    }
+
+    /// The region's left, top, right and bottom edges as texture coordinates
+    /// from 0 to 1
+    pub fn uv(&self, width: u32, height: u32) -> [f32; 4] {
+        let (w, h) = (width as f32, height as f32);
+        [
+            self.x as f32 / w,
+            self.y as f32 / h,
+            (self.x + self.width) as f32 / w,
+            (self.y + self.height) as f32 / h,
+        ]
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(io::Error),
+    Texture(TextureError),
+    /// The description couldn't be parsed, or is missing something
+    Syntax(String),
+    /// The packer stored a frame rotated, which isn't supported. Turn
+    /// rotation off when packing.
+    Rotated(String),
+    /// The description is neither XML nor, with the "json" feature, JSON
+    UnknownFormat,
+    /// An image is too wide to be packed
+    TooWide(String),
+    /// There were no images to pack
+    Empty,
+    /// Two frames have the same name
+    DuplicateFrame(String),
+    /// A frame reaches past the edges of the atlas's image
+    OutOfBounds(String),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::Io(e) => write!(f, "{}", e),
+            AtlasError::Texture(e) => write!(f, "{}", e),
+            AtlasError::Syntax(reason) => write!(f, "Invalid atlas: {}", reason),
+            AtlasError::Rotated(frame) => write!(f, "Frame \"{}\" is rotated, which isn't supported", frame),
+            AtlasError::UnknownFormat => write!(f, "Unknown atlas format"),
+            AtlasError::TooWide(name) => write!(f, "\"{}\" is wider than the atlas", name),
+            AtlasError::Empty => write!(f, "No images to pack"),
+            AtlasError::DuplicateFrame(name) => write!(f, "More than one frame is named \"{}\"", name),
+            AtlasError::OutOfBounds(name) => write!(f, "Frame \"{}\" reaches outside the image", name),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtlasError::Io(e) => Some(e),
+            AtlasError::Texture(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AtlasError {
+    fn from(e: io::Error) -> Self {
+        AtlasError::Io(e)
+    }
+}
+
+impl From<TextureError> for AtlasError {
+    fn from(e: TextureError) -> Self {
+        match e {
+            TextureError::Io(e) => AtlasError::Io(e),
+            e => AtlasError::Texture(e),
+        }
+    }
+}
+
+/// A texture made of named frames, so that many sprites can be drawn from
+/// one texture and batched together. Cheap to clone.
+///
+/// Atlases can be loaded from the XML format most packers can write, with a
+/// `<SubTexture>` for each frame, from their JSON format with the "json"
+/// feature, or packed from loose images with `Atlas::pack`.
+#[derive(Clone, Debug)]
+pub struct Atlas {
+    texture: Texture,
+    frames: Arc<HashMap<String, Region>>,
+    /// The description the atlas was loaded from, if any
+    path: Option<String>,
+}
+
+impl Atlas {
+    pub fn new(texture: Texture, frames: HashMap<String, Region>) -> Self {
+        Atlas {
+            texture,
+            frames: Arc::new(frames),
+            path: None,
+        }
+    }
+
+    /// Makes sure every frame lies within the texture before creating the
+    /// atlas
+    fn checked(texture: Texture, frames: HashMap<String, Region>) -> Result<Self, AtlasError> {
+        let image = texture.image();
+        if let Some((name, _)) = frames.iter().find(|(_, r)| !r.fits(image.width, image.height)) {
+            return Err(AtlasError::OutOfBounds(name.clone()));
+        }
+
+        Ok(Atlas::new(texture, frames))
+    }
+
+    /// Loads a description and the image it names, relative to it. Files
+    /// ending in `.json` are read as JSON, anything else as XML.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AtlasError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut atlas = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Atlas::from_json(&data, dir)?,
+            _ => Atlas::from_xml(&data, dir)?,
+        };
+        atlas.path = Some(path.display().to_string());
+        Ok(atlas)
+    }
+
+    /// Reads a `<TextureAtlas imagePath="...">` description, loading the
+    /// image from `dir`
+    pub fn from_xml(data: &str, dir: &Path) -> Result<Self, AtlasError> {
+        let document = roxmltree::Document::parse(data)
+            .map_err(|e| AtlasError::Syntax(e.to_string()))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "TextureAtlas" {
+            return Err(AtlasError::UnknownFormat);
+        }
+
+        let image = root.attribute("imagePath")
+            .ok_or_else(|| AtlasError::Syntax("missing imagePath".to_string()))?;
+
+        let mut frames = HashMap::new();
+        for n in root.children().filter(|n| n.has_tag_name("SubTexture")) {
+            let name = n.attribute("name")
+                .ok_or_else(|| AtlasError::Syntax("SubTexture without a name".to_string()))?;
+            if n.attribute("rotated") == Some("true") {
+                return Err(AtlasError::Rotated(name.to_string()));
+            }
+
+            let number = |attribute| n.attribute(attribute)
+                .and_then(|v| v.trim().parse::<u32>().ok())
+                .ok_or_else(|| AtlasError::Syntax(format!("invalid {} for \"{}\"", attribute, name)));
+            let region = Region {
+                x: number("x")?,
+                y: number("y")?,
+                width: number("width")?,
+                height: number("height")?,
+            };
+            if frames.insert(name.to_string(), region).is_some() {
+                return Err(AtlasError::DuplicateFrame(name.to_string()));
+            }
+        }
+
+        Atlas::checked(Texture::load(dir.join(image))?, frames)
+    }
+
+    /// Reads a description with a `frames` object or array and the image
+    /// named by `meta.image`, loading the image from `dir`
+    #[cfg(feature = "json")]
+    pub fn from_json(data: &str, dir: &Path) -> Result<Self, AtlasError> {
+        use serde_json::Value;
+
+        let invalid = |reason: &str| AtlasError::Syntax(reason.to_string());
+        let document: Value = serde_json::from_str(data)
+            .map_err(|e| AtlasError::Syntax(e.to_string()))?;
+
+        let image = document.pointer("/meta/image")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("missing meta.image"))?;
+
+        // Packers write frames either keyed by name or as a list with filenames
+        let entries: Vec<(&str, &Value)> = match document.get("frames") {
+            Some(Value::Object(frames)) => frames.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+            Some(Value::Array(frames)) => frames.iter()
+                .map(|v| v.get("filename").and_then(Value::as_str).map(|k| (k, v)))
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid("frame without a filename"))?,
+            _ => return Err(invalid("missing frames")),
+        };
+
+        let mut frames = HashMap::new();
+        for (name, frame) in entries {
+            if frame.get("rotated").and_then(Value::as_bool) == Some(true) {
+                return Err(AtlasError::Rotated(name.to_string()));
+            }
+
+            let number = |key: &str| frame.get("frame")
+                .and_then(|f| f.get(key))
+                .and_then(Value::as_u64)
+                .map(|n| n as u32)
+                .ok_or_else(|| AtlasError::Syntax(format!("invalid {} for \"{}\"", key, name)));
+            let region = Region {
+                x: number("x")?,
+                y: number("y")?,
+                width: number("w")?,
+                height: number("h")?,
+            };
+            if frames.insert(name.to_string(), region).is_some() {
+                return Err(AtlasError::DuplicateFrame(name.to_string()));
+            }
+        }
+
+        Atlas::checked(Texture::load(dir.join(image))?, frames)
+    }
+
+    #[cfg(not(feature = "json"))]
+    pub fn from_json(_: &str, _: &Path) -> Result<Self, AtlasError> {
+        Err(AtlasError::UnknownFormat)
+    }
+
+    /// Packs images into one atlas no wider than `max_width`, naming each
+    /// frame after its image. Frames are kept a pixel apart so filtering
+    /// doesn't bleed between them.
+    pub fn pack(images: &[(String, Image)], max_width: u32) -> Result<Self, AtlasError> {
+        if images.is_empty() {
+            return Err(AtlasError::Empty);
+        }
+
+        // Shelves fill up tightest when the tallest images go first
+        let mut order: Vec<&(String, Image)> = images.iter().collect();
+        order.sort_by(|a, b| b.1.height.cmp(&a.1.height).then(a.0.cmp(&b.0)));
+
+        let mut frames = HashMap::new();
+        let (mut x, mut y, mut shelf) = (0, 0, 0);
+        let mut width = 0;
+        for (name, image) in order.iter() {
+            if image.width > max_width {
+                return Err(AtlasError::TooWide(name.clone()));
+            }
+            if x + image.width > max_width {
+                x = 0;
+                y += shelf + 1;
+                shelf = 0;
+            }
+
+            if frames.insert(name.clone(), Region { x, y, width: image.width, height: image.height }).is_some() {
+                return Err(AtlasError::DuplicateFrame(name.clone()));
+            }
+            x += image.width + 1;
+            shelf = shelf.max(image.height);
+            width = width.max(x - 1);
+        }
+        let height = y + shelf;
+
+        let mut data = vec![0; (width * height * 4) as usize];
+        for (name, image) in order {
+            let region = frames[name];
+            for row in 0..image.height {
+                let src = (row * image.width * 4) as usize;
+                let dst = (((region.y + row) * width + region.x) * 4) as usize;
+                let len = (image.width * 4) as usize;
+                data[dst..dst + len].copy_from_slice(&image.data[src..src + len]);
+            }
+        }
+
+        Ok(Atlas::new(Texture::from_image(Image { width, height, data }), frames))
+    }
+
+    /// Packs image files into one atlas, naming each frame after its file
+    /// without the extension. Meant for build scripts, followed by `save`.
+    pub fn pack_files<P: AsRef<Path>>(paths: &[P], max_width: u32) -> Result<Self, AtlasError> {
+        let mut images = vec![];
+        for path in paths {
+            let path = path.as_ref();
+            let name = path.file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let texture = Texture::load(path)?;
+            images.push((name, texture.image().clone()));
+        }
+
+        Atlas::pack(&images, max_width)
+    }
+
+    /// Writes the atlas's image as a PNG and its description as XML, in the
+    /// format `load` reads. The description names the image by its file name,
+    /// so both should go in the same directory.
+    pub fn save<P: AsRef<Path>, Q: AsRef<Path>>(&self, description: P, image: Q) -> Result<(), AtlasError> {
+        let (description, image) = (description.as_ref(), image.as_ref());
+        self.texture.image().save_png(image)
+            .map_err(io::Error::from)?;
+
+        let relative = image.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        fs::write(description, self.to_xml(&relative))?;
+        Ok(())
+    }
+
+    /// Describes the atlas as `<TextureAtlas>`, frames sorted by name
+    pub fn to_xml(&self, image_path: &str) -> String {
+        let mut names: Vec<&String> = self.frames.keys().collect();
+        names.sort();
+
+        let frames: Vec<String> = names.into_iter()
+            .map(|name| {
+                let r = self.frames[name];
+                write_element("SubTexture", &[
+                    ("name", name.clone()),
+                    ("x", r.x.to_string()),
+                    ("y", r.y.to_string()),
+                    ("width", r.width.to_string()),
+                    ("height", r.height.to_string()),
+                ], &[])
+            })
+            .collect();
+
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", write_element(
+            "TextureAtlas",
+            &[("imagePath", image_path.to_string())],
+            &frames,
+        ))
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn frame(&self, name: &str) -> Option<Region> {
+        self.frames.get(name).copied()
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = (&str, Region)> + '_ {
+        self.frames.iter().map(|(name, region)| (name.as_str(), *region))
+    }
+
+    /// The description the atlas was loaded from, if any
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+}
+
+impl PartialEq for Atlas {
+    fn eq(&self, other: &Self) -> bool {
+        self.texture == other.texture && self.frames == other.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, shade: u8) -> Image {
+        Image { width, height, data: vec![shade; (width * height * 4) as usize] }
+    }
+
+    #[test]
+    fn packs_images_apart_and_copies_their_pixels() {
+        let images = vec![
+            ("a".to_string(), image(4, 8, 10)),
+            ("b".to_string(), image(6, 3, 20)),
+            ("c".to_string(), image(5, 5, 30)),
+        ];
+        let atlas = Atlas::pack(&images, 12).unwrap();
+        let texture = atlas.texture().image();
+
+        for (name, image) in images.iter() {
+            let region = atlas.frame(name).unwrap();
+            assert_eq!((region.width, region.height), (image.width, image.height));
+            assert!(region.x + region.width <= texture.width);
+            assert!(region.y + region.height <= texture.height);
+            assert_eq!(texture.pixel(region.x, region.y), Some([image.data[0]; 4]));
+        }
+
+        // Frames don't touch, so filtering can't bleed between them
+        let frames: Vec<Region> = atlas.frames().map(|(_, r)| r).collect();
+        for (i, a) in frames.iter().enumerate() {
+            for b in frames[i + 1..].iter() {
+                let apart = a.x + a.width < b.x || b.x + b.width < a.x
+                    || a.y + a.height < b.y || b.y + b.height < a.y;
+                assert!(apart, "{:?} touches {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_images_wider_than_the_atlas() {
+        let images = vec![("wide".to_string(), image(13, 1, 0))];
+        assert!(matches!(Atlas::pack(&images, 12), Err(AtlasError::TooWide(name)) if name == "wide"));
+    }
+
+    #[test]
+    fn rejects_empty_input_and_duplicate_names() {
+        assert!(matches!(Atlas::pack(&[], 16), Err(AtlasError::Empty)));
+
+        let images = vec![("a".to_string(), image(2, 2, 0)), ("a".to_string(), image(3, 3, 0))];
+        assert!(matches!(Atlas::pack(&images, 16), Err(AtlasError::DuplicateFrame(name)) if name == "a"));
+    }
+
+    #[test]
+    fn rejects_frames_outside_the_image() {
+        let dir = std::env::temp_dir().join(format!("bastel-atlas-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        image(4, 4, 0).save_png(dir.join("atlas.png")).unwrap();
+
+        let frame = |x, width| format!(
+            r#"<TextureAtlas imagePath="atlas.png"><SubTexture name="f" x="{}" y="0" width="{}" height="4"/></TextureAtlas>"#,
+            x, width,
+        );
+        assert!(Atlas::from_xml(&frame(0, 4), &dir).is_ok());
+        assert!(matches!(Atlas::from_xml(&frame(1, 4), &dir), Err(AtlasError::OutOfBounds(_))));
+        assert!(matches!(Atlas::from_xml(&frame(0, 0), &dir), Err(AtlasError::OutOfBounds(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use crate::components::Component;
+use crate::entity::Entity;
+use crate::world::{EntityId, World};
+
+type Command = Box<dyn FnOnce(&mut World)>;
+
+/// Changes to the world queued up by systems while they iterate over it.
+///
+/// The queue lives in the world as a resource and is applied in order at the
+/// end of every stage. Commands on entities that have been despawned by then
+/// are dropped.
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Commands {
+            queue: vec![],
+        }
+    }
+
+    pub fn spawn(&mut self, entity: Entity) {
+        self.queue.push(Box::new(move |world| { world.spawn_entity(entity); }));
+    }
+
+    pub fn despawn(&mut self, entity: EntityId) {
+        self.queue.push(Box::new(move |world| { world.despawn(entity); }));
+    }
+
+    pub fn add_component(&mut self, entity: EntityId, component: Component) {
+        self.queue.push(Box::new(move |world| { world.add_component(entity, component).ok(); }));
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: EntityId, component: T) {
+        self.queue.push(Box::new(move |world| { world.insert(entity, component).ok(); }));
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: EntityId) {
+        self.queue.push(Box::new(move |world| { world.remove::<T>(entity); }));
+    }
+
+    /// Queues an arbitrary change to the world
+    pub fn add<F: FnOnce(&mut World) + 'static>(&mut self, command: F) {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Applies and empties the world's queue, including anything the commands
+    /// themselves queue up
+    pub fn apply(world: &mut World) {
+        loop {
+            let queue = match world.resource_mut::<Commands>() {
+                Some(mut commands) => std::mem::take(&mut commands.queue),
+                None => return,
+            };
+            if queue.is_empty() { return }
+
+            for command in queue {
+                command(world);
+            }
+        }
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Commands::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Commands::new());
+        world
+    }
+
+    #[test]
+    fn changes_wait_until_applied() {
+        let mut world = world();
+        let entity = world.spawn(vec![]);
+
+        world.commands().insert(entity, Health(3));
+        world.commands().spawn(Entity::named("spawned", vec![]));
+        assert!(!world.has::<Health>(entity));
+        assert_eq!(world.len(), 1);
+
+        Commands::apply(&mut world);
+        assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(3)));
+        assert!(world.find("spawned").is_some());
+        assert!(world.commands().is_empty());
+    }
+
+    #[test]
+    fn drops_commands_on_despawned_entities() {
+        let mut world = world();
+        let entity = world.spawn(vec![]);
+
+        world.commands().despawn(entity);
+        world.commands().insert(entity, Health(1));
+        Commands::apply(&mut world);
+
+        assert!(!world.is_alive(entity));
+        assert!(!world.has::<Health>(entity));
+    }
+
+    #[test]
+    fn applies_commands_queued_by_commands() {
+        let mut world = world();
+        let entity = world.spawn(vec![]);
+
+        world.commands().add(move |world| world.commands().insert(entity, Health(2)));
+        Commands::apply(&mut world);
+
+        assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(2)));
+    }
+}
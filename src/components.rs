@@ -1,25 +1,54 @@
+pub mod animation;
 pub mod audio;
+pub mod camera;
 pub mod collision;
+pub mod custom;
 pub mod moveable;
 pub mod physics;
 pub mod position;
 pub mod sprite;
 
 use crate::components::{
+    animation::AnimationComponent,
     audio::AudioComponent,
+    camera::CameraComponent,
     collision::CollisionComponent,
+    custom::{AnyComponent, CustomComponent},
     moveable::MoveableComponent,
     physics::PhysicsComponent,
     position::PositionComponent,
     sprite::SpriteComponent,
 };
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum Component {
+    Animation(AnimationComponent),
     Audio(AudioComponent),
+    Camera(CameraComponent),
     Collision(CollisionComponent),
     Moveable(MoveableComponent),
     Physics(PhysicsComponent),
     Position(PositionComponent),
     Sprite(SpriteComponent),
-}
\ No newline at end of file
+    Custom(Box<dyn AnyComponent>),
+}
+
+impl Component {
+    pub fn custom<T: CustomComponent>(component: T) -> Self {
+        Component::Custom(Box::new(component))
+    }
+
+    pub fn to_xml(&self) -> String {
+        match self {
+            Component::Animation(c) => c.to_xml(),
+            Component::Audio(c) => c.to_xml(),
+            Component::Camera(c) => c.to_xml(),
+            Component::Collision(c) => c.to_xml(),
+            Component::Moveable(c) => c.to_xml(),
+            Component::Physics(c) => c.to_xml(),
+            Component::Position(c) => c.to_xml(),
+            Component::Sprite(c) => c.to_xml(),
+            Component::Custom(c) => c.to_xml(),
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use crate::atlas::{Atlas, Region};
+use crate::components::Component;
+use crate::xml::{invalid_value, parse_attribute, write_element, LoadOptions, SceneError};
+
+/// What a clip does once it reaches its last frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlayMode {
+    /// Starts over from the first frame
+    Loop,
+    /// Plays backwards to the first frame, then forwards again
+    PingPong,
+    /// Stops on the last frame
+    Once,
+}
+
+/// Which part of the sprite's texture a frame shows
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameSource {
+    /// A named frame of the sprite's atlas
+    Named(String),
+    /// A cell of a sprite sheet laid out in a grid, counting across then down
+    /// from the top left
+    Cell(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub source: FrameSource,
+    /// How long the frame is shown, in seconds
+    pub duration: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clip {
+    pub name: String,
+    pub frames: Vec<Frame>,
+    pub mode: PlayMode,
+}
+
+/// Cycles an entity's `SpriteComponent` through frames of its atlas or
+/// sprite sheet. Played by the `AnimationSystem`, which sends an
+/// `AnimationFinished` event when a clip ends, or completes a cycle if it
+/// repeats.
+///
+/// Saved scenes keep where each animation is, so they carry on from the
+/// same frame when loaded.
+#[derive(Clone, PartialEq)]
+pub struct AnimationComponent {
+    pub clips: Vec<Clip>,
+    /// The size of a cell of the sprite sheet in pixels, for frames that
+    /// give a cell rather than a name
+    pub cell_size: (u32, u32),
+    /// Multiplies how fast every clip plays
+    pub speed: f32,
+    clip: usize,
+    frame: usize,
+    elapsed: f32,
+    reversing: bool,
+    finished: bool,
+    changed: bool,
+}
+
+impl AnimationComponent {
+    /// Starts playing the first clip
+    pub fn new(clips: Vec<Clip>, cell_size: (u32, u32)) -> Component {
+        Component::Animation(AnimationComponent::with_clips(clips, cell_size))
+    }
+
+    fn with_clips(clips: Vec<Clip>, cell_size: (u32, u32)) -> Self {
+        AnimationComponent {
+            clips,
+            cell_size,
+            speed: 1.0,
+            clip: 0,
+            frame: 0,
+            elapsed: 0.0,
+            reversing: false,
+            finished: false,
+            changed: true,
+        }
+    }
+
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+        let mut cell_size = (0, 0);
+        let mut speed = 1.0;
+        let mut playing = None;
+        let mut frame = None;
+        let mut elapsed = 0.0;
+        let mut reversing = false;
+        let mut finished = false;
+
+        for a in data.attributes() {
+            match a.name() {
+                "cell-width" => cell_size.0 = parse_attribute::<u32>(data, &a)?,
+                "cell-height" => cell_size.1 = parse_attribute::<u32>(data, &a)?,
+                "speed" => match parse_attribute::<f32>(data, &a)? {
+                    s if s >= 0.0 => speed = s,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                "clip" => playing = Some(a),
+                "frame" => frame = Some(a),
+                "elapsed" => match parse_attribute::<f32>(data, &a)? {
+                    e if e >= 0.0 => elapsed = e,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                "reversing" => reversing = parse_attribute::<bool>(data, &a)?,
+                "finished" => finished = parse_attribute::<bool>(data, &a)?,
+                _ => options.unknown_attribute(data, &a)?,
+            }
+        }
+
+        let mut clips = vec![];
+        for n in data.children().filter(|n| n.is_element()) {
+            match n.tag_name().name() {
+                "clip" => clips.push(clip_from_xml(n, options)?),
+                _ => options.unknown_element(n)?,
+            }
+        }
+
+        let mut animation = AnimationComponent::with_clips(clips, cell_size);
+        animation.speed = speed;
+        if let Some(a) = playing {
+            if !animation.play(a.value()) {
+                return Err(invalid_value(data, &a));
+            }
+        }
+
+        // Where a saved animation was, within the clip it was playing
+        if let Some(a) = frame {
+            let len = animation.clip().map_or(0, |c| c.frames.len());
+            match parse_attribute::<usize>(data, &a)? {
+                f if f < len => animation.frame = f,
+                _ => return Err(invalid_value(data, &a)),
+            }
+        }
+        animation.elapsed = elapsed;
+        animation.reversing = reversing;
+        animation.finished = finished;
+
+        Ok(Component::Animation(animation))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = vec![];
+        if let Some(clip) = self.clips.get(self.clip) {
+            attributes.push(("clip", clip.name.clone()));
+        }
+        if self.cell_size != (0, 0) {
+            attributes.push(("cell-width", self.cell_size.0.to_string()));
+            attributes.push(("cell-height", self.cell_size.1.to_string()));
+        }
+        if self.speed != 1.0 {
+            attributes.push(("speed", self.speed.to_string()));
+        }
+        if self.frame != 0 { attributes.push(("frame", self.frame.to_string())) }
+        if self.elapsed != 0.0 { attributes.push(("elapsed", self.elapsed.to_string())) }
+        if self.reversing { attributes.push(("reversing", "true".to_string())) }
+        if self.finished { attributes.push(("finished", "true".to_string())) }
+
+        let clips: Vec<String> = self.clips.iter().map(clip_to_xml).collect();
+
+        write_element("animation", &attributes, &clips)
+    }
+
+    /// Switches to a clip from its first frame, unless it's already playing.
+    /// Returns false if there's no such clip.
+    pub fn play(&mut self, name: &str) -> bool {
+        let clip = match self.clips.iter().position(|c| c.name == name) {
+            Some(c) => c,
+            None => return false,
+        };
+
+        if clip != self.clip || self.finished {
+            self.clip = clip;
+            self.restart();
+        }
+        true
+    }
+
+    /// Plays the current clip again from its first frame
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.reversing = false;
+        self.finished = false;
+        self.changed = true;
+    }
+
+    pub fn clip(&self) -> Option<&Clip> {
+        self.clips.get(self.clip)
+    }
+
+    pub fn frame(&self) -> Option<&Frame> {
+        self.clip().and_then(|c| c.frames.get(self.frame))
+    }
+
+    /// Whether a clip played `Once` has reached its end
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The part of a texture of the given size the current frame shows, if it
+    /// is a cell of a sprite sheet
+    pub fn cell_region(&self, texture_size: (u32, u32)) -> Option<Region> {
+        let cell = match self.frame()?.source {
+            FrameSource::Cell(cell) => cell,
+            FrameSource::Named(_) => return None,
+        };
+
+        let (width, height) = self.cell_size;
+        let columns = texture_size.0.checked_div(width)?.max(1);
+        Some(Region {
+            x: cell % columns * width,
+            y: cell / columns * height,
+            width,
+            height,
+        })
+    }
+
+    /// Moves on by `delta` seconds, returning whether the clip ended or
+    /// completed a cycle
+    pub fn advance(&mut self, delta: f32) -> bool {
+        let (mode, len) = match self.clip() {
+            Some(c) if !c.frames.is_empty() && !self.finished => (c.mode, c.frames.len()),
+            _ => return false,
+        };
+
+        let mut completed = false;
+        self.elapsed += delta * self.speed;
+        loop {
+            let duration = self.clips[self.clip].frames[self.frame].duration;
+            if duration <= 0.0 || self.elapsed < duration { break }
+            self.elapsed -= duration;
+
+            let previous = self.frame;
+            match mode {
+                PlayMode::Loop => {
+                    self.frame = (self.frame + 1) % len;
+                    completed |= self.frame == 0;
+                },
+                PlayMode::Once if self.frame + 1 == len => {
+                    self.finished = true;
+                    self.elapsed = 0.0;
+                    return true;
+                },
+                PlayMode::Once => self.frame += 1,
+                PlayMode::PingPong if len == 1 => completed = true,
+                PlayMode::PingPong => {
+                    if self.reversing && self.frame == 0 {
+                        self.reversing = false;
+                    } else if !self.reversing && self.frame + 1 == len {
+                        self.reversing = true;
+                    }
+
+                    match self.reversing {
+                        true => self.frame -= 1,
+                        false => self.frame += 1,
+                    }
+                    completed |= self.frame == 0;
+                },
+            }
+            self.changed |= self.frame != previous;
+        }
+
+        completed
+    }
+
+    /// Whether the frame has changed since this was last called
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+}
+
+fn clip_from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Clip, SceneError> {
+    let mut name = None;
+    let mut mode = PlayMode::Loop;
+    let mut duration = 0.1;
+
+    for a in data.attributes() {
+        match a.name() {
+            "name" => name = Some(String::from(a.value())),
+            "mode" => match a.value() {
+                "loop" => mode = PlayMode::Loop,
+                "ping-pong" => mode = PlayMode::PingPong,
+                "once" => mode = PlayMode::Once,
+                _ => return Err(invalid_value(data, &a)),
+            },
+            "duration" => duration = parse_duration(data, &a)?,
+            _ => options.unknown_attribute(data, &a)?,
+        }
+    }
+
+    let mut frames = vec![];
+    for n in data.children().filter(|n| n.is_element()) {
+        match n.tag_name().name() {
+            "frame" => frames.push(frame_from_xml(n, options, duration)?),
+            _ => options.unknown_element(n)?,
+        }
+    }
+
+    Ok(Clip {
+        name: name.ok_or_else(|| SceneError::missing_attribute(data, "name"))?,
+        frames,
+        mode,
+    })
+}
+
+/// Reads a `<frame>` with either a `name` or a sprite sheet `cell`, shown for
+/// its own `duration` or the clip's
+fn frame_from_xml(data: roxmltree::Node, options: &LoadOptions, mut duration: f32) -> Result<Frame, SceneError> {
+    let mut source = None;
+
+    for a in data.attributes() {
+        match a.name() {
+            "name" => source = Some(FrameSource::Named(String::from(a.value()))),
+            "cell" => source = Some(FrameSource::Cell(parse_attribute::<u32>(data, &a)?)),
+            "duration" => duration = parse_duration(data, &a)?,
+            _ => options.unknown_attribute(data, &a)?,
+        }
+    }
+
+    Ok(Frame {
+        source: source.ok_or_else(|| SceneError::missing_attribute(data, "name or cell"))?,
+        duration,
+    })
+}
+
+/// Checks that every named frame of an `<animation>` is in `atlas`, the atlas
+/// of the entity's sprite, which is only known once the whole entity is read
+pub fn check_frame_names(data: roxmltree::Node, atlas: Option<&Atlas>) -> Result<(), SceneError> {
+    for frame in data.descendants().filter(|n| n.has_tag_name("frame")) {
+        let name = match frame.attributes().find(|a| a.name() == "name") {
+            Some(a) => a,
+            None => continue,
+        };
+
+        if atlas.and_then(|a| a.frame(name.value())).is_none() {
+            return Err(invalid_value(frame, &name));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_duration(data: roxmltree::Node, attribute: &roxmltree::Attribute) -> Result<f32, SceneError> {
+    match parse_attribute::<f32>(data, attribute)? {
+        d if d > 0.0 => Ok(d),
+        _ => Err(invalid_value(data, attribute)),
+    }
+}
+
+/// Writes every frame's duration, rather than working out the clip's
+fn clip_to_xml(clip: &Clip) -> String {
+    let mode = match clip.mode {
+        PlayMode::Loop => "loop",
+        PlayMode::PingPong => "ping-pong",
+        PlayMode::Once => "once",
+    };
+
+    let frames: Vec<String> = clip.frames.iter()
+        .map(|f| {
+            let source = match &f.source {
+                FrameSource::Named(name) => ("name", name.clone()),
+                FrameSource::Cell(cell) => ("cell", cell.to_string()),
+            };
+            write_element("frame", &[source, ("duration", f.duration.to_string())], &[])
+        })
+        .collect();
+
+    write_element("clip", &[("name", clip.name.clone()), ("mode", mode.to_string())], &frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::Image;
+    use crate::scene::Scene;
+
+    fn animation(mode: PlayMode, frames: u32) -> AnimationComponent {
+        let frames = (0..frames)
+            .map(|cell| Frame { source: FrameSource::Cell(cell), duration: 0.1 })
+            .collect();
+        AnimationComponent::with_clips(vec![Clip { name: "clip".to_string(), frames, mode }], (16, 16))
+    }
+
+    /// Advances one frame's duration at a time, noting the frame shown after
+    /// each step and whether the clip ended or completed a cycle
+    fn play(animation: &mut AnimationComponent, steps: usize) -> Vec<(u32, bool)> {
+        (0..steps)
+            .map(|_| {
+                let completed = animation.advance(0.1);
+                match animation.frame().unwrap().source {
+                    FrameSource::Cell(cell) => (cell, completed),
+                    FrameSource::Named(_) => unreachable!(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loops() {
+        let mut animation = animation(PlayMode::Loop, 3);
+        assert_eq!(play(&mut animation, 4), [(1, false), (2, false), (0, true), (1, false)]);
+    }
+
+    #[test]
+    fn ping_pongs() {
+        let mut animation = animation(PlayMode::PingPong, 3);
+        assert_eq!(
+            play(&mut animation, 6),
+            [(1, false), (2, false), (1, false), (0, true), (1, false), (2, false)],
+        );
+    }
+
+    #[test]
+    fn stops_on_the_last_frame_once() {
+        let mut animation = animation(PlayMode::Once, 2);
+        assert_eq!(play(&mut animation, 3), [(1, false), (1, true), (1, false)]);
+        assert!(animation.is_finished());
+
+        animation.restart();
+        assert!(!animation.is_finished());
+        assert_eq!(play(&mut animation, 1), [(1, false)]);
+    }
+
+    #[test]
+    fn carries_time_over_between_frames() {
+        let mut animation = animation(PlayMode::Loop, 4);
+        animation.speed = 2.0;
+        assert!(!animation.advance(0.125));
+        assert_eq!(animation.frame().unwrap().source, FrameSource::Cell(2));
+        assert!(animation.take_changed());
+        assert!(!animation.take_changed());
+    }
+
+    #[test]
+    fn names_both_ways_of_choosing_a_frame_when_neither_is_given() {
+        let error = Scene::from_xml(r#"<scene><entity>
+            <animation><clip name="idle"><frame duration="1"/></clip></animation>
+        </entity></scene>"#).err().unwrap();
+        assert!(error.to_string().contains("<frame name or cell>: Missing attribute"), "{}", error);
+    }
+
+    #[test]
+    fn checks_named_frames_against_the_sprite_atlas_when_strict() {
+        let dir = std::env::temp_dir().join(format!("bastel-animation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = Image { width: 2, height: 2, data: vec![255; 16] };
+        Atlas::pack(&[("idle".to_string(), image)], 16).unwrap()
+            .save(dir.join("atlas.xml"), dir.join("atlas.png"))
+            .unwrap();
+
+        let xml = format!(r#"<scene><entity>
+            <sprite atlas="{}" frame="idle"/>
+            <animation><clip name="idle"><frame name="idle"/><frame name="walk"/></clip></animation>
+        </entity></scene>"#, dir.join("atlas.xml").display());
+
+        assert!(Scene::from_xml(&xml).is_ok());
+        let error = Scene::from_xml_with(&xml, &LoadOptions::strict()).err().unwrap();
+        assert_eq!((error.line, error.attribute.as_deref()), (3, Some("name")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,8 +1,8 @@
 use crate::components::Component;
-use roxmltree;
+use crate::xml::{parse_attribute, write_element, LoadOptions, SceneError};
 
 #[allow(dead_code)]
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct AudioComponent {
     pub muted: bool,
     pub bgm: Option<String>,
@@ -20,26 +20,35 @@ impl AudioComponent {
         )
     }
 
-    pub fn from_xml(data: roxmltree::Node) -> Component {
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
         let mut muted = false;
         let mut bgm = None;
+        let mut sfx = None;
 
-        data.attributes()
-            .map(|a| {
-                match a.name() {
-                    "muted" => muted = true,
-                    "bgm" => bgm = Some(String::from(a.value())),
-                    _ => (),
-                }
+        for a in data.attributes() {
+            match a.name() {
+                "muted" => muted = parse_attribute::<bool>(data, &a)?,
+                "bgm" => bgm = Some(String::from(a.value())),
+                "sfx" => sfx = Some(String::from(a.value())),
+                _ => options.unknown_attribute(data, &a)?,
             }
-        ).for_each(drop);
+        }
 
-        Component::Audio(
+        Ok(Component::Audio(
             AudioComponent {
                 muted,
                 bgm,
-                sfx: None,
+                sfx,
             }
-        )
+        ))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = vec![];
+        if self.muted { attributes.push(("muted", "true".to_string())) }
+        if let Some(bgm) = &self.bgm { attributes.push(("bgm", bgm.clone())) }
+        if let Some(sfx) = &self.sfx { attributes.push(("sfx", sfx.clone())) }
+
+        write_element("audio", &attributes, &[])
     }
 }
\ No newline at end of file
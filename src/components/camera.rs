@@ -0,0 +1,326 @@
+use crate::components::Component;
+use crate::xml::{invalid_value, parse_attribute, parse_degrees, write_degrees, write_element, LoadOptions, SceneError};
+
+/// A column-major 4x4 matrix, laid out as GLSL expects a `mat4`
+pub type Matrix = [[f32; 4]; 4];
+
+/// A rectangle in world units, given by its top left corner and size
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Self, SceneError> {
+        let mut rect = Rect { x: 0.0, y: 0.0, width: 0.0, height: 0.0 };
+
+        for a in data.attributes() {
+            match a.name() {
+                "x" => rect.x = parse_attribute::<f32>(data, &a)?,
+                "y" => rect.y = parse_attribute::<f32>(data, &a)?,
+                "width" => rect.width = parse_attribute::<f32>(data, &a)?,
+                "height" => rect.height = parse_attribute::<f32>(data, &a)?,
+                _ => options.unknown_attribute(data, &a)?,
+            }
+        }
+
+        Ok(rect)
+    }
+
+    fn to_xml(self, tag: &str) -> String {
+        write_element(tag, &[
+            ("x", self.x.to_string()),
+            ("y", self.y.to_string()),
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+        ], &[])
+    }
+}
+
+/// A shake fading out over its duration
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Shake {
+    strength: f32,
+    duration: f32,
+    remaining: f32,
+}
+
+impl Shake {
+    fn to_xml(self, offset: (f32, f32)) -> String {
+        write_element("shake", &[
+            ("strength", self.strength.to_string()),
+            ("duration", self.duration.to_string()),
+            ("remaining", self.remaining.to_string()),
+            ("offset-x", offset.0.to_string()),
+            ("offset-y", offset.1.to_string()),
+        ], &[])
+    }
+}
+
+/// The view a scene is drawn from, in world units. A scene is seen through
+/// the camera of the earliest spawned entity that has one, or with the origin
+/// at the top left of the view without one.
+///
+/// The camera can follow a named entity, only moving once the entity leaves
+/// the deadzone around the middle of the view, and can be kept from showing
+/// anything outside of its bounds. Both are applied by the `CameraSystem`.
+#[derive(Clone, PartialEq)]
+pub struct CameraComponent {
+    /// The point in the middle of the view
+    pub position: (f32, f32),
+    /// How many times larger things appear, 2 shows half as much of the world
+    pub zoom: f32,
+    /// Clockwise, in radians
+    pub rotation: f32,
+    /// The name of the entity to keep in view
+    pub follow: Option<String>,
+    /// The size of a rectangle centred on the camera that the followed entity
+    /// moves freely within
+    pub deadzone: (f32, f32),
+    /// Roughly how many seconds the camera takes to catch up with the
+    /// followed entity, 0 to keep up immediately
+    pub smoothing: f32,
+    /// The part of the world the view is kept within
+    pub bounds: Option<Rect>,
+    shake: Option<Shake>,
+    shake_offset: (f32, f32),
+}
+
+impl CameraComponent {
+    pub fn new(position: (f32, f32)) -> Component {
+        Component::Camera(
+            CameraComponent {
+                position,
+                ..CameraComponent::default()
+            }
+        )
+    }
+
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+        let mut camera = CameraComponent::default();
+
+        for a in data.attributes() {
+            match a.name() {
+                "x" => camera.position.0 = parse_attribute::<f32>(data, &a)?,
+                "y" => camera.position.1 = parse_attribute::<f32>(data, &a)?,
+                "zoom" => match parse_attribute::<f32>(data, &a)? {
+                    z if z > 0.0 => camera.zoom = z,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                "rotation" => camera.rotation = parse_degrees(data, &a)?,
+                "follow" => camera.follow = Some(String::from(a.value())),
+                "smoothing" => match parse_attribute::<f32>(data, &a)? {
+                    s if s >= 0.0 => camera.smoothing = s,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                _ => options.unknown_attribute(data, &a)?,
+            }
+        }
+
+        for n in data.children().filter(|n| n.is_element()) {
+            match n.tag_name().name() {
+                "deadzone" => camera.deadzone = deadzone_from_xml(n, options)?,
+                "bounds" => camera.bounds = Some(Rect::from_xml(n, options)?),
+                "shake" => (camera.shake, camera.shake_offset) = shake_from_xml(n, options)?,
+                _ => options.unknown_element(n)?,
+            }
+        }
+
+        Ok(Component::Camera(camera))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = vec![
+            ("x", self.position.0.to_string()),
+            ("y", self.position.1.to_string()),
+        ];
+        if self.zoom != 1.0 { attributes.push(("zoom", self.zoom.to_string())) }
+        if self.rotation != 0.0 { attributes.push(("rotation", write_degrees(self.rotation))) }
+        if let Some(follow) = &self.follow { attributes.push(("follow", follow.clone())) }
+        if self.smoothing != 0.0 { attributes.push(("smoothing", self.smoothing.to_string())) }
+
+        let mut children = vec![];
+        if self.deadzone != (0.0, 0.0) {
+            children.push(write_element("deadzone", &[
+                ("width", self.deadzone.0.to_string()),
+                ("height", self.deadzone.1.to_string()),
+            ], &[]));
+        }
+        if let Some(bounds) = self.bounds { children.push(bounds.to_xml("bounds")) }
+        if let Some(shake) = self.shake { children.push(shake.to_xml(self.shake_offset)) }
+
+        write_element("camera", &attributes, &children)
+    }
+
+    /// Shakes the view by up to `strength` world units, fading out over
+    /// `duration` seconds. Replaces a weaker shake in progress.
+    pub fn shake(&mut self, strength: f32, duration: f32) {
+        let current = self.shake.map_or(0.0, |s| s.strength * s.remaining / s.duration);
+        if strength >= current && duration > 0.0 {
+            self.shake = Some(Shake { strength, duration, remaining: duration });
+        }
+    }
+
+    pub fn is_shaking(&self) -> bool {
+        self.shake.is_some()
+    }
+
+    /// Where the view is centred this frame, including any shake
+    pub fn eye(&self) -> (f32, f32) {
+        (self.position.0 + self.shake_offset.0, self.position.1 + self.shake_offset.1)
+    }
+
+    /// Moves towards the followed entity's centre, then back inside the bounds.
+    /// `view` is the size of the world seen at zoom 1.
+    pub fn update(&mut self, target: Option<(f32, f32)>, view: (f32, f32), delta: f32) {
+        if let Some(target) = target {
+            // Only the part of the offset that's outside the deadzone is made up
+            let (dx, dy) = (target.0 - self.position.0, target.1 - self.position.1);
+            let (hw, hh) = (self.deadzone.0 / 2.0, self.deadzone.1 / 2.0);
+            let goal = (
+                self.position.0 + dx - dx.clamp(-hw, hw),
+                self.position.1 + dy - dy.clamp(-hh, hh),
+            );
+
+            let t = match self.smoothing > 0.0 {
+                true => 1.0 - (-delta / self.smoothing).exp(),
+                false => 1.0,
+            };
+            self.position.0 += (goal.0 - self.position.0) * t;
+            self.position.1 += (goal.1 - self.position.1) * t;
+        }
+
+        if let Some(bounds) = self.bounds {
+            let (hw, hh) = self.half_extents(view);
+            self.position.0 = clamp_to(self.position.0, bounds.x, bounds.width, hw);
+            self.position.1 = clamp_to(self.position.1, bounds.y, bounds.height, hh);
+        }
+
+        self.update_shake(delta);
+    }
+
+    fn update_shake(&mut self, delta: f32) {
+        let shake = match &mut self.shake {
+            Some(s) => s,
+            None => {
+                self.shake_offset = (0.0, 0.0);
+                return;
+            },
+        };
+
+        shake.remaining -= delta;
+        if shake.remaining <= 0.0 {
+            self.shake = None;
+            self.shake_offset = (0.0, 0.0);
+            return;
+        }
+
+        // Sums of sines at unrelated frequencies wander without repeating
+        // noticeably, and need no random number generator
+        let t = shake.duration - shake.remaining;
+        let amplitude = shake.strength * shake.remaining / shake.duration;
+        self.shake_offset = (
+            amplitude * ((t * 71.0).sin() + (t * 113.0).sin()) / 2.0,
+            amplitude * ((t * 89.0).sin() + (t * 127.0).cos()) / 2.0,
+        );
+    }
+
+    /// Half the size of the world in view, ignoring rotation
+    pub fn half_extents(&self, view: (f32, f32)) -> (f32, f32) {
+        (view.0 / 2.0 / self.zoom, view.1 / 2.0 / self.zoom)
+    }
+
+    /// The matrix taking world positions to clip space. `view` is the size of
+    /// the world seen at zoom 1.
+    pub fn view_projection(&self, view: (f32, f32)) -> Matrix {
+        let (ex, ey) = self.eye();
+        let (sin, cos) = (-self.rotation).sin_cos();
+        let (hw, hh) = self.half_extents(view);
+        let (sx, sy) = (1.0 / hw, 1.0 / hh);
+
+        [
+            [sx * cos, sy * sin, 0.0, 0.0],
+            [-sx * sin, sy * cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-sx * (cos * ex - sin * ey), -sy * (sin * ex + cos * ey), 0.0, 1.0],
+        ]
+    }
+
+    /// Converts a point from -1 to 1 across and down the view, such as a
+    /// click, to world units
+    pub fn to_world(&self, point: (f32, f32), view: (f32, f32)) -> (f32, f32) {
+        let (ex, ey) = self.eye();
+        let (sin, cos) = self.rotation.sin_cos();
+        let (hw, hh) = self.half_extents(view);
+        let (x, y) = (point.0 * hw, point.1 * hh);
+
+        (ex + cos * x - sin * y, ey + sin * x + cos * y)
+    }
+}
+
+impl Default for CameraComponent {
+    fn default() -> Self {
+        CameraComponent {
+            position: (0.0, 0.0),
+            zoom: 1.0,
+            rotation: 0.0,
+            follow: None,
+            deadzone: (0.0, 0.0),
+            smoothing: 0.0,
+            bounds: None,
+            shake: None,
+            shake_offset: (0.0, 0.0),
+        }
+    }
+}
+
+fn deadzone_from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<(f32, f32), SceneError> {
+    let mut size = (0.0, 0.0);
+
+    for a in data.attributes() {
+        match a.name() {
+            "width" => size.0 = parse_attribute::<f32>(data, &a)?,
+            "height" => size.1 = parse_attribute::<f32>(data, &a)?,
+            _ => options.unknown_attribute(data, &a)?,
+        }
+    }
+
+    Ok(size)
+}
+
+/// Reads a shake in progress and how far it has moved the view
+fn shake_from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<(Option<Shake>, (f32, f32)), SceneError> {
+    let mut shake = Shake { strength: 0.0, duration: 0.0, remaining: 0.0 };
+    let mut offset = (0.0, 0.0);
+
+    for a in data.attributes() {
+        match a.name() {
+            "strength" => shake.strength = parse_attribute::<f32>(data, &a)?,
+            "duration" => match parse_attribute::<f32>(data, &a)? {
+                d if d > 0.0 => shake.duration = d,
+                _ => return Err(invalid_value(data, &a)),
+            },
+            "remaining" => shake.remaining = parse_attribute::<f32>(data, &a)?,
+            "offset-x" => offset.0 = parse_attribute::<f32>(data, &a)?,
+            "offset-y" => offset.1 = parse_attribute::<f32>(data, &a)?,
+            _ => options.unknown_attribute(data, &a)?,
+        }
+    }
+
+    if shake.duration <= 0.0 {
+        return Err(SceneError::missing_attribute(data, "duration"));
+    }
+    Ok((Some(shake), offset))
+}
+
+/// Keeps a view of half size `half` centred on `centre` within `start` to
+/// `start + length`, centring it when the view is the larger of the two
+fn clamp_to(centre: f32, start: f32, length: f32, half: f32) -> f32 {
+    match length > half * 2.0 {
+        true => centre.clamp(start + half, start + length - half),
+        false => start + length / 2.0,
+    }
+}
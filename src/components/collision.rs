@@ -1,6 +1,7 @@
 use crate::components::Component;
+use crate::xml::{write_element, LoadOptions, SceneError};
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct CollisionComponent { }
 
 impl CollisionComponent {
@@ -9,4 +10,16 @@ impl CollisionComponent {
             CollisionComponent {}
         )
     }
-}
\ No newline at end of file
+
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+        for a in data.attributes() {
+            options.unknown_attribute(data, &a)?;
+        }
+
+        Ok(CollisionComponent::new())
+    }
+
+    pub fn to_xml(&self) -> String {
+        write_element("collision", &[], &[])
+    }
+}
@@ -0,0 +1,76 @@
+use crate::components::Component;
+use crate::world::{EntityId, NoSuchEntity, World};
+use crate::xml::{LoadOptions, SceneError};
+
+use std::any::Any;
+
+/// A component type defined outside the engine, such as health or AI state.
+///
+/// Any type can be stored in a `World` and queried, implementing this trait
+/// lets it also be put in an `Entity`, loaded from scene XML once registered
+/// with `LoadOptions::register` and saved again by `Scene::to_xml`.
+pub trait CustomComponent: Clone + PartialEq + 'static {
+    /// The element the component is written as under an `<entity>`, which
+    /// can't be one of the built-in ones
+    const TAG: &'static str;
+
+    fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Self, SceneError>;
+
+    fn to_xml(&self) -> String;
+}
+
+/// A `CustomComponent` with its type erased, so it can be kept in a
+/// `Component`. Implemented for every `CustomComponent`.
+pub trait AnyComponent {
+    fn as_any(&self) -> &dyn Any;
+
+    fn clone_box(&self) -> Box<dyn AnyComponent>;
+
+    fn eq_dyn(&self, other: &dyn AnyComponent) -> bool;
+
+    fn to_xml(&self) -> String;
+
+    fn insert(self: Box<Self>, world: &mut World, entity: EntityId) -> Result<(), NoSuchEntity>;
+}
+
+impl<T: CustomComponent> AnyComponent for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn AnyComponent> {
+        Box::new(self.clone())
+    }
+
+    fn eq_dyn(&self, other: &dyn AnyComponent) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+
+    fn to_xml(&self) -> String {
+        CustomComponent::to_xml(self)
+    }
+
+    fn insert(self: Box<Self>, world: &mut World, entity: EntityId) -> Result<(), NoSuchEntity> {
+        world.register_component::<T>();
+        world.insert(entity, *self)
+    }
+}
+
+impl Clone for Box<dyn AnyComponent> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl PartialEq for dyn AnyComponent {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_dyn(other)
+    }
+}
+
+/// Builds a component from an element, see `LoadOptions::register`
+pub type ComponentParser = fn(roxmltree::Node, &LoadOptions) -> Result<Component, SceneError>;
+
+pub(crate) fn parse<T: CustomComponent>(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+    Ok(Component::custom(T::from_xml(data, options)?))
+}
@@ -1,12 +1,51 @@
 use crate::components::Component;
+use crate::xml::{invalid_value, parse_attribute, write_element, LoadOptions, SceneError};
 
-#[derive(PartialEq)]
-pub struct MoveableComponent { }
+/// Lets the player push an entity around with the arrow keys
+#[derive(Clone, PartialEq)]
+pub struct MoveableComponent {
+    /// The force applied each simulation step while a key is held, in world
+    /// units
+    pub strength: f32,
+}
 
 impl MoveableComponent {
+    pub const DEFAULT_STRENGTH: f32 = 0.5;
+
     pub fn new() -> Component {
         Component::Moveable(
-            MoveableComponent {}
+            MoveableComponent {
+                strength: MoveableComponent::DEFAULT_STRENGTH,
+            }
         )
     }
-}
\ No newline at end of file
+
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+        let mut strength = MoveableComponent::DEFAULT_STRENGTH;
+
+        for a in data.attributes() {
+            match a.name() {
+                "strength" => match parse_attribute::<f32>(data, &a)? {
+                    s if s >= 0.0 => strength = s,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                _ => options.unknown_attribute(data, &a)?,
+            }
+        }
+
+        Ok(Component::Moveable(
+            MoveableComponent {
+                strength,
+            }
+        ))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = vec![];
+        if self.strength != MoveableComponent::DEFAULT_STRENGTH {
+            attributes.push(("strength", self.strength.to_string()));
+        }
+
+        write_element("moveable", &attributes, &[])
+    }
+}
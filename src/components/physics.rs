@@ -1,6 +1,7 @@
 use crate::components::Component;
+use crate::xml::{invalid_value, parse_attribute, write_element, LoadOptions, SceneError};
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct PhysicsComponent {
     pub mass: f32,
     pub acceleration: (f32, f32),
@@ -72,41 +73,40 @@ impl PhysicsComponent {
         self.acceleration = (0.0, 0.0);
     }
 
-    pub fn from_xml(data: roxmltree::Node) -> Component {
-        let mut mass = 0.0;
-        let mut friction = 0.0;
-        let mut bounciness = 0.0;
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+        let mut physics = PhysicsComponent::default();
 
-        data.attributes()
-            .map(|a| {
-                match a.name() {
-                    "mass" => match a.value().parse::<f32>() {
-                        Ok(d) => mass = d,
-                        Err(_) => (),
-                    },
-                    "friction" => match a.value().parse::<f32>() {
-                        Ok(d) => friction = d,
-                        Err(_) => (),
-                    },
-                    "bounciness" => match a.value().parse::<f32>() {
-                        Ok(d) => bounciness = d,
-                        Err(_) => (),
-                    },
-                    _ => (),
-                }
+        for a in data.attributes() {
+            match a.name() {
+                "mass" => {
+                    physics.mass = parse_attribute::<f32>(data, &a)?;
+                    if physics.mass <= 0.0 { return Err(invalid_value(data, &a)) }
+                },
+                "friction" => physics.friction = parse_attribute::<f32>(data, &a)?,
+                "bounciness" => physics.bounciness = parse_attribute::<f32>(data, &a)?,
+                "vx" => physics.velocity.0 = parse_attribute::<f32>(data, &a)?,
+                "vy" => physics.velocity.1 = parse_attribute::<f32>(data, &a)?,
+                "ax" => physics.acceleration.0 = parse_attribute::<f32>(data, &a)?,
+                "ay" => physics.acceleration.1 = parse_attribute::<f32>(data, &a)?,
+                "airtime" => physics.airtime = parse_attribute::<u32>(data, &a)?,
+                _ => options.unknown_attribute(data, &a)?,
             }
-        ).for_each(drop);
+        }
 
-        Component::Physics(
-            PhysicsComponent {
-                mass,
-                friction,
-                bounciness,
-                acceleration: (0.0, 0.0),
-                velocity: (0.0, 0.0),
-                airtime: 0,
-            }
-        )
+        Ok(Component::Physics(physics))
     }
 
-}
\ No newline at end of file
+    /// Also writes the runtime state, so saved games resume mid-motion
+    pub fn to_xml(&self) -> String {
+        write_element("physics", &[
+            ("mass", self.mass.to_string()),
+            ("friction", self.friction.to_string()),
+            ("bounciness", self.bounciness.to_string()),
+            ("vx", self.velocity.0.to_string()),
+            ("vy", self.velocity.1.to_string()),
+            ("ax", self.acceleration.0.to_string()),
+            ("ay", self.acceleration.1.to_string()),
+            ("airtime", self.airtime.to_string()),
+        ], &[])
+    }
+}
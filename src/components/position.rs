@@ -1,115 +1,229 @@
 use crate::components::Component;
 use crate::vertex::Vertex;
+use crate::xml::{parse_attribute, parse_degrees, write_degrees, write_element, LoadOptions, SceneError};
 
-#[derive(PartialEq)]
+/// Where an entity is and how it is turned, in world units.
+///
+/// `position` and `size` describe the untransformed box by its top left
+/// corner. It is scaled and rotated around its pivot, and flipping mirrors
+/// what's drawn in it without moving it. Collisions use the axis-aligned box
+/// around the result.
+#[derive(Clone, PartialEq)]
 pub struct PositionComponent {
     pub position: (f32, f32),
     pub size: (f32, f32),
+    /// Clockwise, in radians
+    pub rotation: f32,
+    pub scale: (f32, f32),
+    /// The point rotation and scale happen around, as a fraction of the size
+    /// from the top left
+    pub pivot: (f32, f32),
+    pub flip: (bool, bool),
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u16>,
 }
 
 impl PositionComponent {
     pub fn new(position: (f32, f32), size: (f32, f32)) -> Component {
-        Component::Position(
-            PositionComponent {
-                vertices: generate_vertices(position, size),
-                indices: generate_indices(),
-                position,
-                size,
-            }
-        )
-    }
-
-    pub fn from_xml(data: roxmltree::Node) -> Component {
-        let mut x = f32::NAN;
-        let mut y = f32::NAN;
-        let mut width = f32::NAN;
-        let mut height = f32::NAN;
-
-        data.attributes()
-            .map(|a| {
-                match a.name() {
-                    "x" => match a.value().parse::<f32>() {
-                        Ok(d) => x = d,
-                        Err(_) => (),
-                    },
-                    "y" => match a.value().parse::<f32>() {
-                        Ok(d) => y = d,
-                        Err(_) => (),
-                    },
-                    "width" => match a.value().parse::<f32>() {
-                        Ok(d) => width = d,
-                        Err(_) => (),
-                    },
-                    "height" => match a.value().parse::<f32>() {
-                        Ok(d) => height = d,
-                        Err(_) => (),
-                    },
-                    _ => (),
-                }
-            }
-        ).for_each(drop);
+        let mut c = PositionComponent {
+            position,
+            size,
+            rotation: 0.0,
+            scale: (1.0, 1.0),
+            pivot: (0.5, 0.5),
+            flip: (false, false),
+            vertices: vec![],
+            indices: generate_indices(),
+        };
+        c.update_vertices();
 
-        let position = (x, y);
-        let size = (width, height);
+        Component::Position(c)
+    }
 
-        Component::Position(
-            PositionComponent {
-                vertices: generate_vertices(position, size),
-                indices: generate_indices(),
-                position,
-                size,
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
+        let mut x = None;
+        let mut y = None;
+        let mut width = None;
+        let mut height = None;
+        let mut rotation = 0.0;
+        let mut scale = (1.0, 1.0);
+        let mut pivot = (0.5, 0.5);
+        let mut flip = (false, false);
+
+        for a in data.attributes() {
+            match a.name() {
+                "x" => x = Some(parse_attribute::<f32>(data, &a)?),
+                "y" => y = Some(parse_attribute::<f32>(data, &a)?),
+                "width" => width = Some(parse_attribute::<f32>(data, &a)?),
+                "height" => height = Some(parse_attribute::<f32>(data, &a)?),
+                "rotation" => rotation = parse_degrees(data, &a)?,
+                "scale" => {
+                    let s = parse_attribute::<f32>(data, &a)?;
+                    scale = (s, s);
+                },
+                "scale-x" => scale.0 = parse_attribute::<f32>(data, &a)?,
+                "scale-y" => scale.1 = parse_attribute::<f32>(data, &a)?,
+                "pivot-x" => pivot.0 = parse_attribute::<f32>(data, &a)?,
+                "pivot-y" => pivot.1 = parse_attribute::<f32>(data, &a)?,
+                "flip-x" => flip.0 = parse_attribute::<bool>(data, &a)?,
+                "flip-y" => flip.1 = parse_attribute::<bool>(data, &a)?,
+                _ => options.unknown_attribute(data, &a)?,
             }
-        )
+        }
+
+        let require = |value: Option<f32>, name| value.ok_or_else(|| SceneError::missing_attribute(data, name));
+        let position = (require(x, "x")?, require(y, "y")?);
+        let size = (require(width, "width")?, require(height, "height")?);
+
+        let mut c = PositionComponent {
+            position,
+            size,
+            rotation,
+            scale,
+            pivot,
+            flip,
+            vertices: vec![],
+            indices: generate_indices(),
+        };
+        c.update_vertices();
+
+        Ok(Component::Position(c))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = vec![
+            ("x", self.position.0.to_string()),
+            ("y", self.position.1.to_string()),
+            ("width", self.size.0.to_string()),
+            ("height", self.size.1.to_string()),
+        ];
+        if self.rotation != 0.0 { attributes.push(("rotation", write_degrees(self.rotation))) }
+        if self.scale.0 != 1.0 { attributes.push(("scale-x", self.scale.0.to_string())) }
+        if self.scale.1 != 1.0 { attributes.push(("scale-y", self.scale.1.to_string())) }
+        if self.pivot.0 != 0.5 { attributes.push(("pivot-x", self.pivot.0.to_string())) }
+        if self.pivot.1 != 0.5 { attributes.push(("pivot-y", self.pivot.1.to_string())) }
+        if self.flip.0 { attributes.push(("flip-x", "true".to_string())) }
+        if self.flip.1 { attributes.push(("flip-y", "true".to_string())) }
+
+        write_element("position", &attributes, &[])
+    }
+
+    pub fn set_position(&mut self, position: (f32, f32)) {
+        self.position = position;
+        self.update_vertices();
     }
 
     pub fn shift(&mut self, x: f32, y: f32) {
         self.position.0 += x;
         self.position.1 += y;
-        self.vertices = generate_vertices(self.position, self.size);
+        self.update_vertices();
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+        self.update_vertices();
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.rotation += angle;
+        self.update_vertices();
+    }
+
+    pub fn set_scale(&mut self, scale: (f32, f32)) {
+        self.scale = scale;
+        self.update_vertices();
+    }
+
+    pub fn set_pivot(&mut self, pivot: (f32, f32)) {
+        self.pivot = pivot;
+        self.update_vertices();
+    }
+
+    pub fn set_flip(&mut self, horizontal: bool, vertical: bool) {
+        self.flip = (horizontal, vertical);
+        self.update_vertices();
+    }
+
+    /// Rebuilds the vertices after the public fields were changed directly
+    pub fn update_vertices(&mut self) {
+        self.vertices = generate_vertices(self);
     }
 
     pub fn get_left_pos(&self) -> f32 {
-        self.position.0
+        self.corners().iter().map(|c| c.0).fold(f32::INFINITY, f32::min)
     }
 
     pub fn get_right_pos(&self) -> f32 {
-        self.position.0 + self.size.0
+        self.corners().iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max)
     }
 
     pub fn get_top_pos(&self) -> f32 {
-        self.position.1
+        self.corners().iter().map(|c| c.1).fold(f32::INFINITY, f32::min)
     }
 
     pub fn get_bottom_pos(&self) -> f32 {
-        self.position.1 + self.size.1
+        self.corners().iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max)
+    }
+
+    /// The left, right, top and bottom edges of the box around the
+    /// transformed entity, cheaper than asking for each edge on its own
+    pub fn bounds(&self) -> [f32; 4] {
+        let corners = self.corners();
+        let xs = corners.map(|c| c.0);
+        let ys = corners.map(|c| c.1);
+
+        [
+            xs.into_iter().fold(f32::INFINITY, f32::min),
+            xs.into_iter().fold(f32::NEG_INFINITY, f32::max),
+            ys.into_iter().fold(f32::INFINITY, f32::min),
+            ys.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        ]
+    }
+
+    /// The middle of the box after it's been transformed
+    pub fn get_centre(&self) -> (f32, f32) {
+        let [left, right, top, bottom] = self.bounds();
+        ((left + right) / 2.0, (top + bottom) / 2.0)
+    }
+
+    /// The transformed top left, bottom left, bottom right and top right
+    /// corners
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let (w, h) = self.size;
+        let pivot = (self.pivot.0 * w, self.pivot.1 * h);
+        let (sin, cos) = self.rotation.sin_cos();
+
+        [(0.0, 0.0), (0.0, h), (w, h), (w, 0.0)].map(|(x, y)| {
+            let x = (x - pivot.0) * self.scale.0;
+            let y = (y - pivot.1) * self.scale.1;
+            (
+                self.position.0 + pivot.0 + x * cos - y * sin,
+                self.position.1 + pivot.1 + x * sin + y * cos,
+            )
+        })
     }
 }
 
-fn generate_vertices(position: (f32, f32), size: (f32, f32)) -> Vec<Vertex> {
-    vec!(
-        Vertex {
-            position: [position.0, position.1],
-            color: [0.0, 0.0, 0.0],
-            uv: [0.0, 0.0],
-        },
-        Vertex {
-            position: [position.0, position.1 + size.1],
-            color: [0.0, 0.0, 0.0],
-            uv: [0.0, 1.0],
-        },
-        Vertex {
-            position: [position.0 + size.0, position.1 + size.1],
-            color: [0.0, 0.0, 0.0],
-            uv: [1.0, 1.0],
-        },
-        Vertex {
-            position: [position.0 + size.0, position.1],
-            color: [0.0, 0.0, 0.0],
-            uv: [1.0, 0.0],
-        },
-    )
+fn generate_vertices(c: &PositionComponent) -> Vec<Vertex> {
+    let (u0, u1) = match c.flip.0 {
+        true => (1.0, 0.0),
+        false => (0.0, 1.0),
+    };
+    let (v0, v1) = match c.flip.1 {
+        true => (1.0, 0.0),
+        false => (0.0, 1.0),
+    };
+    let uvs = [[u0, v0], [u0, v1], [u1, v1], [u1, v0]];
+
+    c.corners()
+        .iter()
+        .zip(uvs)
+        .map(|(corner, uv)| Vertex {
+            position: [corner.0, corner.1],
+            color: [1.0, 1.0, 1.0, 1.0],
+            uv,
+        })
+        .collect()
 }
 
 fn generate_indices() -> Vec<u16> {
@@ -1,49 +1,245 @@
 use crate::shaders::Shader;
+use crate::atlas::{Atlas, AtlasError, Region};
 use crate::components::Component;
+use crate::layers::Layers;
+use crate::texture::{Texture, TextureError};
+use crate::xml::{invalid_value, parse_attribute, write_element, ErrorKind, LoadOptions, SceneError};
 
-use std::fs;
-use std::num::ParseIntError;
-use std::path::Path;
+/// RGBA from 0 to 1 that leaves whatever it multiplies unchanged
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct SpriteComponent {
     pub shader: Shader,
-    pub color: [f32; 3],
-    pub texture: Option<Vec<u8>>,
+    /// The fill of solid sprites, as linear RGBA from 0 to 1
+    pub color: [f32; 4],
+    /// Multiplies the output of every shader, e.g. to flash a textured
+    /// sprite red
+    pub tint: [f32; 4],
+    /// Multiplies the alpha of the color and tint, from 0 (invisible) to 1
+    pub opacity: f32,
+    pub texture: Option<Texture>,
+    /// The part of the texture that's drawn, all of it if `None`
+    pub source: Option<Region>,
+    /// Where `texture` and `source` came from if the sprite shows a frame of
+    /// an atlas, along with the frame's name
+    pub atlas: Option<(Atlas, String)>,
+    /// The name of the scene's `Layers` the sprite is drawn in
+    pub layer: String,
+    /// Sprites with a higher z are drawn in front, within a layer
+    pub z: f32,
 }
 
 impl SpriteComponent {
-    pub fn from_xml(data: roxmltree::Node) -> Component {
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Component, SceneError> {
         let mut shader = Shader::Rainbow;
-        let mut color = "#000000";
+        let mut color = WHITE;
+        let mut tint = WHITE;
+        let mut opacity = 1.0;
         let mut texture = None;
+        let mut source = Region { x: 0, y: 0, width: 0, height: 0 };
+        let mut source_attributes = vec![];
+        let mut atlas = None;
+        let mut frame = None;
+        let mut layer = Layers::DEFAULT_LAYER.to_string();
+        let mut z = 0.0;
 
-        data.attributes()
-            .map(|a| {
-                match a.name() {
-                    "shader" => match a.value() {
-                        "solid" => shader = Shader::Solid,
-                        "texture" => shader = Shader::Texture,
-                        "rainbow" => shader = Shader::Rainbow,
-                        _ => shader = Shader::Rainbow,
+        for a in data.attributes() {
+            match a.name() {
+                "shader" => match a.value() {
+                    "solid" => shader = Shader::Solid,
+                    "texture" => shader = Shader::Texture,
+                    "rainbow" => shader = Shader::Rainbow,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                "color" => color = decode_color(a.value()).ok_or_else(|| invalid_value(data, &a))?,
+                "tint" => tint = decode_color(a.value()).ok_or_else(|| invalid_value(data, &a))?,
+                "opacity" => match parse_attribute::<f32>(data, &a)? {
+                    o if (0.0..=1.0).contains(&o) => opacity = o,
+                    _ => return Err(invalid_value(data, &a)),
+                },
+                "texture" => match Texture::load(a.value()) {
+                    Ok(t) => texture = Some(t),
+                    Err(TextureError::Io(e)) => return Err(SceneError::at_attribute(data, &a, ErrorKind::File(a.value().to_string(), e))),
+                    Err(e) => return Err(SceneError::at_attribute(data, &a, ErrorKind::Texture(a.value().to_string(), e))),
+                },
+                "source-x" => {
+                    source.x = parse_attribute::<u32>(data, &a)?;
+                    source_attributes.push(a);
+                },
+                "source-y" => {
+                    source.y = parse_attribute::<u32>(data, &a)?;
+                    source_attributes.push(a);
+                },
+                "source-width" => match parse_attribute::<u32>(data, &a)? {
+                    0 => return Err(invalid_value(data, &a)),
+                    width => {
+                        source.width = width;
+                        source_attributes.push(a);
                     },
-                    "color" => color = a.value(),
-                    "texture" => texture = fs::read(Path::new(a.value())).ok(),
-                    _ => (),
+                },
+                "source-height" => match parse_attribute::<u32>(data, &a)? {
+                    0 => return Err(invalid_value(data, &a)),
+                    height => {
+                        source.height = height;
+                        source_attributes.push(a);
+                    },
+                },
+                "atlas" => match Atlas::load(a.value()) {
+                    Ok(loaded) => atlas = Some(loaded),
+                    Err(AtlasError::Io(e)) => return Err(SceneError::at_attribute(data, &a, ErrorKind::File(a.value().to_string(), e))),
+                    Err(e) => return Err(SceneError::at_attribute(data, &a, ErrorKind::Atlas(a.value().to_string(), e))),
+                },
+                "frame" => frame = Some(a),
+                "layer" => layer = String::from(a.value()),
+                "z" => z = parse_attribute::<f32>(data, &a)?,
+                _ => options.unknown_attribute(data, &a)?,
+            }
+        }
+
+        let mut sprite = SpriteComponent {
+            shader,
+            color,
+            tint,
+            opacity,
+            texture,
+            source: None,
+            atlas: None,
+            layer,
+            z,
+        };
+
+        // A source rectangle is all or nothing, and atlas frames bring their own
+        if let Some(a) = source_attributes.first() {
+            for (name, conflict) in [("atlas", atlas.is_some()), ("frame", frame.is_some())] {
+                if conflict {
+                    return Err(SceneError::at_attribute(data, a, ErrorKind::Conflict(name.to_string())));
                 }
             }
-        ).for_each(drop);
 
-        let color = decode_hex(color).unwrap_or(vec![0.0, 0.0, 0.0]);
-        let color = [color[0], color[1], color[2]];
+            for name in ["source-x", "source-y", "source-width", "source-height"] {
+                if !source_attributes.iter().any(|a| a.name() == name) {
+                    return Err(SceneError::missing_attribute(data, name));
+                }
+            }
 
-        Component::Sprite(
-            SpriteComponent {
-                shader,
-                color,
-                texture,
+            if let Some(texture) = &sprite.texture {
+                let image = texture.image();
+                let find = |name| source_attributes.iter().find(|a| a.name() == name).unwrap();
+                if source.x.checked_add(source.width).is_none_or(|right| right > image.width) {
+                    return Err(invalid_value(data, find("source-width")));
+                }
+                if source.y.checked_add(source.height).is_none_or(|bottom| bottom > image.height) {
+                    return Err(invalid_value(data, find("source-height")));
+                }
             }
-        )
+        }
+
+        match (atlas, frame) {
+            (Some(atlas), Some(frame)) => {
+                sprite.atlas = Some((atlas, String::new()));
+                if !sprite.set_frame(frame.value()) {
+                    return Err(invalid_value(data, &frame));
+                }
+            },
+            (Some(_), None) => return Err(SceneError::missing_attribute(data, "frame")),
+            (None, Some(_)) => return Err(SceneError::missing_attribute(data, "atlas")),
+            (None, None) => if !source_attributes.is_empty() { sprite.source = Some(source) },
+        }
+
+        Ok(Component::Sprite(sprite))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let shader = match self.shader {
+            Shader::Solid => "solid",
+            Shader::Texture => "texture",
+            Shader::Rainbow => "rainbow",
+        };
+
+        let mut attributes = vec![
+            ("shader", shader.to_string()),
+            ("color", encode_color(&self.color)),
+        ];
+        if self.tint != WHITE {
+            attributes.push(("tint", encode_color(&self.tint)));
+        }
+        if self.opacity != 1.0 {
+            attributes.push(("opacity", self.opacity.to_string()));
+        }
+        if self.layer != Layers::DEFAULT_LAYER {
+            attributes.push(("layer", self.layer.clone()));
+        }
+        if self.z != 0.0 {
+            attributes.push(("z", self.z.to_string()));
+        }
+        match &self.atlas {
+            Some((atlas, frame)) => {
+                if let Some(path) = atlas.path() {
+                    attributes.push(("atlas", path.to_string()));
+                    attributes.push(("frame", frame.clone()));
+                }
+            },
+            None => {
+                if let Some(path) = self.texture.as_ref().and_then(|t| t.path()) {
+                    attributes.push(("texture", path.display().to_string()));
+                }
+                if let Some(source) = self.source {
+                    attributes.push(("source-x", source.x.to_string()));
+                    attributes.push(("source-y", source.y.to_string()));
+                    attributes.push(("source-width", source.width.to_string()));
+                    attributes.push(("source-height", source.height.to_string()));
+                }
+            },
+        }
+
+        write_element("sprite", &attributes, &[])
+    }
+
+    /// Shows a frame of the sprite's atlas, returning false if it has no atlas
+    /// or the atlas has no such frame
+    pub fn set_frame(&mut self, name: &str) -> bool {
+        let (atlas, frame) = match &mut self.atlas {
+            Some(a) => a,
+            None => return false,
+        };
+        let region = match atlas.frame(name) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        *frame = name.to_string();
+        self.texture = Some(atlas.texture().clone());
+        self.source = Some(region);
+        true
+    }
+
+    /// The edges of the part of the texture that's drawn, as texture
+    /// coordinates from 0 to 1
+    pub fn uv_rect(&self) -> [f32; 4] {
+        match (&self.texture, self.source) {
+            (Some(texture), Some(source)) => {
+                let image = texture.image();
+                source.uv(image.width, image.height)
+            },
+            _ => [0.0, 0.0, 1.0, 1.0],
+        }
+    }
+
+    /// The color given to the sprite's vertices: the fill for solid sprites
+    /// and the multiplier for the rest, tinted and faded
+    pub fn vertex_color(&self) -> [f32; 4] {
+        let base = match self.shader {
+            Shader::Solid => self.color,
+            Shader::Texture | Shader::Rainbow => WHITE,
+        };
+
+        let mut color = [0.0; 4];
+        for (i, c) in color.iter_mut().enumerate() {
+            *c = base[i] * self.tint[i];
+        }
+        color[3] *= self.opacity;
+        color
     }
 
     /*
@@ -172,12 +368,153 @@ impl SpriteComponent {
     */
 }
 
-fn decode_hex(s: &str) -> Result<Vec<f32>, ParseIntError> {
-    (1..s.len())
-        .step_by(2)
-        .map(|i| match u8::from_str_radix(&s[i..i + 2], 16) {
-            Ok(n) => Ok(n as f32),
-            Err(e) => Err(e),
+/// Parses a color as linear RGBA from 0 to 1, given either as `#RRGGBB` or
+/// `#RRGGBBAA` in sRGB like colors on the web, or as three or four linear
+/// values from 0 to 1 separated by spaces. Alpha is linear either way.
+fn decode_color(s: &str) -> Option<[f32; 4]> {
+    match s.strip_prefix('#') {
+        Some(digits) => decode_hex(digits),
+        None => decode_linear(s),
+    }
+}
+
+fn decode_hex(digits: &str) -> Option<[f32; 4]> {
+    if !(digits.len() == 6 || digits.len() == 8) || !digits.is_ascii() { return None }
+
+    let mut color = WHITE;
+    for (i, c) in color.iter_mut().enumerate().take(digits.len() / 2) {
+        let value = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).ok()? as f32 / 255.0;
+        *c = match i {
+            3 => value,
+            _ => srgb_to_linear(value),
+        };
+    }
+    Some(color)
+}
+
+fn decode_linear(s: &str) -> Option<[f32; 4]> {
+    let values = s.split_whitespace()
+        .map(|v| v.parse::<f32>().ok().filter(|v| (0.0..=1.0).contains(v)))
+        .collect::<Option<Vec<f32>>>()?;
+    if !(values.len() == 3 || values.len() == 4) { return None }
+
+    let mut color = WHITE;
+    color[..values.len()].copy_from_slice(&values);
+    Some(color)
+}
+
+/// Writes the color as hex if that reads back exactly, and as linear values
+/// otherwise, so saving never changes a color
+fn encode_color(color: &[f32; 4]) -> String {
+    let hex = encode_hex(color);
+    if decode_color(&hex).as_ref() == Some(color) {
+        return hex;
+    }
+
+    let channels = match color[3] >= 1.0 {
+        true => &color[..3],
+        false => &color[..],
+    };
+    channels.iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Writes `#RRGGBB`, or `#RRGGBBAA` if the color isn't opaque
+fn encode_hex(color: &[f32; 4]) -> String {
+    let channels = match color[3] >= 1.0 {
+        true => 3,
+        false => 4,
+    };
+
+    color.iter()
+        .enumerate()
+        .take(channels)
+        .map(|(i, c)| match i {
+            3 => *c,
+            _ => linear_to_srgb(*c),
         })
-        .collect()
-}
\ No newline at end of file
+        .fold(String::from("#"), |s, c| s + &format!("{:02x}", (c * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    match c <= 0.0031308 {
+        true => c * 12.92,
+        false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::Image;
+    use crate::scene::Scene;
+    use std::path::PathBuf;
+
+    /// Writes a 4x4 image and an atlas with a frame covering it
+    fn files() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bastel-sprite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = Image { width: 4, height: 4, data: vec![255; 64] };
+        image.save_png(dir.join("image.png")).unwrap();
+        Atlas::pack(&[("all".to_string(), image)], 16).unwrap()
+            .save(dir.join("atlas.xml"), dir.join("atlas.png"))
+            .unwrap();
+        dir
+    }
+
+    fn load(dir: &std::path::Path, attributes: &str) -> Result<Scene, SceneError> {
+        let attributes = attributes.replace("DIR", &dir.display().to_string());
+        Scene::from_xml(&format!("<scene><entity><sprite {}/></entity></scene>", attributes))
+    }
+
+    #[test]
+    fn reads_hex_colors_as_srgb() {
+        let gray = decode_color("#808080").unwrap();
+        assert!((gray[0] - 0.2159).abs() < 1e-4, "{:?}", gray);
+        assert_eq!(decode_color("#ffffff80").unwrap()[3], 128.0 / 255.0);
+        assert_eq!(decode_color("0.25 0.5 1"), Some([0.25, 0.5, 1.0, 1.0]));
+        assert_eq!(decode_color("#12345"), None);
+        assert_eq!(decode_color("0.5 2 0"), None);
+    }
+
+    #[test]
+    fn writes_colors_that_read_back_exactly() {
+        for color in [decode_color("#808080").unwrap(), [0.3, 0.1, 0.7, 1.0], [0.5, 0.5, 0.5, 0.25]] {
+            assert_eq!(decode_color(&encode_color(&color)), Some(color));
+        }
+        assert_eq!(encode_color(&decode_color("#80ff0040").unwrap()), "#80ff0040");
+    }
+
+    #[test]
+    fn takes_only_whole_source_rectangles_within_the_texture() {
+        let dir = files();
+        let texture = r#"shader="texture" texture="DIR/image.png""#;
+
+        let whole = format!(r#"{} source-x="1" source-y="1" source-width="3" source-height="3""#, texture);
+        assert!(load(&dir, &whole).is_ok());
+
+        let partial = format!(r#"{} source-width="2""#, texture);
+        assert_eq!(load(&dir, &partial).err().unwrap().attribute.as_deref(), Some("source-x"));
+
+        let outside = format!(r#"{} source-x="2" source-y="0" source-width="3" source-height="4""#, texture);
+        assert_eq!(load(&dir, &outside).err().unwrap().attribute.as_deref(), Some("source-width"));
+
+        let empty = format!(r#"{} source-x="0" source-y="0" source-width="0" source-height="4""#, texture);
+        assert!(load(&dir, &empty).is_err());
+
+        let mixed = r#"atlas="DIR/atlas.xml" frame="all" source-x="0" source-y="0" source-width="1" source-height="1""#;
+        let error = load(&dir, mixed).err().unwrap();
+        assert!(matches!(error.kind, ErrorKind::Conflict(_)), "{}", error);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
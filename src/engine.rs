@@ -1,13 +1,13 @@
+pub mod builder;
+
+use crate::engine::builder::EngineBuilder;
+use crate::events::{PlayBgm, Resized};
 use crate::global::Global;
-use crate::renderer::Renderer;
-use crate::scene::Scene;
-use crate::systems::System;
+use crate::scene::stack::{ScenePass, SceneStack};
+use crate::schedule::{Schedule, Stage};
 use crate::systems::input::InputSystem;
-use crate::systems::render::RenderSystem;
-use crate::systems::audio::AudioSystem;
-use crate::systems::physics::PhysicsSystem;
-use crate::systems::movement::MovementSystem;
-use crate::systems::collision::CollisionSystem;
+use crate::time::{FixedTimestep, Time};
+use crate::world::World;
 
 use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
@@ -16,44 +16,51 @@ use winit::event_loop::{ControlFlow, EventLoop};
 
 pub struct Engine {
     pub global: Arc<Mutex<Global>>,
-    pub scene: Scene,
+    pub scenes: SceneStack,
     pub fps: u64,
+    pub schedule: Schedule,
+    pub timestep: FixedTimestep,
     input: InputSystem,
-    renderer: Renderer,
+    last_frame: Option<Instant>,
+    headless: bool,
 }
 
 impl Engine {
+    /// Creates an engine running all of the built-in systems
     pub fn new(title: &str, width: u32, height: u32) -> (Self, EventLoop<()>) {
-        let global = Global::new(
-            title.to_string(),
-            (width, height),
-        );
-
-        let (renderer, event_loop) = Renderer::init(title, width, height);
-        let input = InputSystem::new(global.clone());
-        let fps = 60;
-
-        (Engine {
-            global,
-            scene: Scene::new(vec![]),
-            fps,
-            input,
-            renderer,
-        }, event_loop)
+        EngineBuilder::new(title, width, height)
+            .build()
+            .expect("Built-in systems have conflicting constraints")
+    }
+
+    pub fn builder(title: &str, width: u32, height: u32) -> EngineBuilder {
+        EngineBuilder::new(title, width, height)
+    }
+
+    /// The active scene's world
+    pub fn world(&self) -> Option<&World> {
+        self.scenes.active().map(|s| &s.world)
+    }
+
+    pub fn world_mut(&mut self) -> Option<&mut World> {
+        self.scenes.active_mut().map(|s| &mut s.world)
+    }
+
+    /// Advances the engine by `frames` frames of exactly one simulation step
+    /// each, without waiting in between. Mainly for headless engines, whose
+    /// state can then be inspected through `world`.
+    pub fn run_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.advance(self.timestep.step());
+        }
     }
 
     pub fn run(mut self, event_loop: EventLoop<()>) {
         let freq_millis = 1000 / self.fps;
 
-        // Initialize systems
-        let systems: Vec<Box<dyn System>> = vec![
-            Box::new(RenderSystem::new(self.renderer, self.global.clone())),
-            Box::new(AudioSystem::new(self.global.clone())),
-            Box::new(PhysicsSystem::new(self.global.clone())),
-            Box::new(MovementSystem::new(self.global.clone())),
-            Box::new(CollisionSystem::new()),
-        ];
-        for system in systems { self.scene.add_system(system) }
+        if let Some(scene) = self.scenes.active() {
+            scene.world.send_event(PlayBgm);
+        }
 
         // Event loop
         event_loop.run(move |event, _, control_flow| {
@@ -74,7 +81,12 @@ impl Engine {
                     let global = self.global.clone();
                     let mut global = global.lock().expect("Could not unlock global object");
                     global.window_size = (size.width, size.height);
-                    global.signals.insert("resize".to_string(), true);
+                    if let Some(scene) = self.scenes.active() {
+                        scene.world.send_event(Resized {
+                            width: size.width,
+                            height: size.height,
+                        });
+                    }
                 },
 
                 Event::WindowEvent {
@@ -83,7 +95,11 @@ impl Engine {
                         ..
                     },
                     ..
-                } => { self.input.handle_input(input); }
+                } => {
+                    if let Some(scene) = self.scenes.active() {
+                        self.input.handle_input(input, &scene.world);
+                    }
+                }
 
                 Event::WindowEvent {
                     event: WindowEvent::MouseInput {
@@ -91,7 +107,11 @@ impl Engine {
                         ..
                     },
                     ..
-                } => { self.input.click(); },
+                } => {
+                    if let Some(scene) = self.scenes.active() {
+                        self.input.click(&scene.world);
+                    }
+                },
 
                 Event::WindowEvent {
                     event: WindowEvent::CursorMoved {
@@ -102,12 +122,71 @@ impl Engine {
                 } => { self.input.cursor_moved(position); }
 
                 Event::RedrawEventsCleared => {
-                    for system in self.scene.systems.iter_mut() {
-                        system.run(&mut self.scene.entities);
+                    let now = Instant::now();
+                    let delta = now - self.last_frame.replace(now).unwrap_or(now);
+                    self.advance(delta);
+
+                    // Popping the last scene ends the game
+                    if self.scenes.is_empty() {
+                        *control_flow = ControlFlow::Exit;
                     }
                 },
                 _ => (),
             }
         });
     }
-}
\ No newline at end of file
+
+    /// Runs as many fixed simulation steps of the active scene as fit into
+    /// `delta`, then renders every visible scene once and applies any scene
+    /// transitions. Events are only cleared out on frames that stepped the
+    /// simulation, so none are lost on frames too short to step.
+    pub fn advance(&mut self, delta: Duration) {
+        let world = match self.scenes.active_mut() {
+            Some(scene) => &mut scene.world,
+            None => return,
+        };
+        let step = self.timestep.step();
+        self.timestep.accumulate(delta);
+
+        let mut stepped = false;
+        while self.timestep.expend() {
+            if let Some(mut time) = world.resource_mut::<Time>() {
+                time.delta = step;
+                time.fixed_delta = step;
+            }
+
+            self.schedule.run_simulation(world);
+            stepped = true;
+
+            if let Some(mut time) = world.resource_mut::<Time>() {
+                time.elapsed += step;
+            }
+        }
+
+        if let Some(mut time) = world.resource_mut::<Time>() {
+            time.delta = delta;
+            time.frame_delta = delta;
+            time.alpha = self.timestep.alpha();
+        }
+
+        if !self.headless {
+            let count = self.scenes.visible_mut().count();
+            for (index, scene) in self.scenes.visible_mut().enumerate() {
+                scene.world.insert_resource(ScenePass { index, count });
+                self.schedule.run_stage(Stage::Render, &mut scene.world);
+            }
+        }
+
+        if let Some(scene) = self.scenes.active_mut() {
+            if let Some(mut time) = scene.world.resource_mut::<Time>() {
+                time.frame_count += 1;
+            }
+
+            if stepped {
+                scene.world.update_events();
+            }
+        }
+
+        self.scenes.update();
+    }
+}
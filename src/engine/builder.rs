@@ -0,0 +1,333 @@
+use crate::engine::Engine;
+use crate::global::Global;
+use crate::renderer::Renderer;
+use crate::scene::Scene;
+use crate::scene::stack::{SceneSetup, SceneStack};
+use crate::schedule::{Schedule, ScheduleError, Stage, SystemDescriptor};
+use crate::systems::System;
+use crate::systems::input::InputSystem;
+use crate::systems::render::RenderSystem;
+use crate::systems::animation::AnimationSystem;
+use crate::systems::audio::AudioSystem;
+use crate::systems::camera::CameraSystem;
+use crate::systems::physics::PhysicsSystem;
+use crate::systems::movement::MovementSystem;
+use crate::systems::collision::CollisionSystem;
+use crate::systems::transform::TransformSystem;
+use crate::time::FixedTimestep;
+use crate::world::World;
+use crate::xml::LoadOptions;
+
+use std::any::type_name;
+use std::time::Duration;
+use winit::event_loop::EventLoop;
+
+/// The systems the engine provides out of the box, all enabled by default
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum BuiltinSystem {
+    Render,
+    Audio,
+    Physics,
+    Movement,
+    Collision,
+    Transform,
+    Camera,
+    Animation,
+}
+
+impl BuiltinSystem {
+    pub const ALL: [BuiltinSystem; 8] = [
+        BuiltinSystem::Render,
+        BuiltinSystem::Audio,
+        BuiltinSystem::Physics,
+        BuiltinSystem::Movement,
+        BuiltinSystem::Collision,
+        BuiltinSystem::Transform,
+        BuiltinSystem::Camera,
+        BuiltinSystem::Animation,
+    ];
+}
+
+enum SystemEdit {
+    Add(SystemDescriptor),
+    Remove(&'static str),
+    Replace(&'static str, SystemDescriptor),
+}
+
+/// Configures which systems an `Engine` runs and what it starts with.
+///
+/// Edits are applied in the order they were made, after the enabled built-in
+/// systems have been created, so built-ins can be removed or replaced too.
+/// Systems run in the update stage unless added with a `SystemDescriptor`
+/// that says otherwise.
+pub struct EngineBuilder {
+    title: String,
+    size: (u32, u32),
+    view_size: Option<(f32, f32)>,
+    fps: u64,
+    fixed_step: Duration,
+    scene: Scene,
+    options: LoadOptions,
+    builtins: Vec<BuiltinSystem>,
+    edits: Vec<SystemEdit>,
+    setup: Vec<SceneSetup>,
+}
+
+impl EngineBuilder {
+    pub fn new(title: &str, width: u32, height: u32) -> Self {
+        EngineBuilder {
+            title: title.to_string(),
+            size: (width, height),
+            view_size: None,
+            fps: 60,
+            fixed_step: Duration::from_secs(1) / 60,
+            scene: Scene::new(vec![]),
+            options: LoadOptions::default(),
+            builtins: BuiltinSystem::ALL.to_vec(),
+            edits: vec![],
+            setup: vec![],
+        }
+    }
+
+    /// Sets the virtual resolution, how many world units fit across and down
+    /// the view. Defaults to the window size, making a world unit one pixel
+    /// until the window is resized. Panics unless both are positive and finite.
+    pub fn view_size(mut self, width: f32, height: f32) -> Self {
+        assert!([width, height].iter().all(|s| s.is_finite() && *s > 0.0), "view size must be positive and finite");
+        self.view_size = Some((width, height));
+        self
+    }
+
+    pub fn fps(mut self, fps: u64) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    /// Sets how many simulation steps run per second, independently of `fps`.
+    /// Panics if `hz` is 0.
+    pub fn tick_rate(mut self, hz: u32) -> Self {
+        assert!(hz > 0, "tick rate must be at least 1 step per second");
+        self.fixed_step = Duration::from_secs(1) / hz;
+        self
+    }
+
+    /// Sets the scene the engine starts in, at the bottom of the scene stack
+    pub fn scene(mut self, scene: Scene) -> Self {
+        self.scene = scene;
+        self
+    }
+
+    /// Sets how scenes requested by path at runtime are loaded, including
+    /// which custom components they may contain
+    pub fn load_options(mut self, options: LoadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn enable(mut self, system: BuiltinSystem) -> Self {
+        if !self.builtins.contains(&system) {
+            self.builtins.push(system);
+        }
+        self
+    }
+
+    /// Stops a built-in system from being created at all, e.g. to run without
+    /// an audio device
+    pub fn disable(mut self, system: BuiltinSystem) -> Self {
+        self.builtins.retain(|s| *s != system);
+        self
+    }
+
+    pub fn add_system(mut self, system: impl Into<SystemDescriptor>) -> Self {
+        self.edits.push(SystemEdit::Add(system.into()));
+        self
+    }
+
+    pub fn remove_system<S: System>(mut self) -> Self {
+        self.edits.push(SystemEdit::Remove(type_name::<S>()));
+        self
+    }
+
+    /// Swaps the system of type `S` for `system`, which takes over its stage
+    /// and ordering constraints
+    pub fn replace_system<S: System, R: System + 'static>(mut self, system: R) -> Self {
+        self.edits.push(SystemEdit::Replace(type_name::<S>(), SystemDescriptor::new(system)));
+        self
+    }
+
+    /// Gives every scene the engine runs a copy of `resource`, unless the
+    /// scene already has one of its own
+    pub fn insert_resource<T: Clone + 'static>(mut self, resource: T) -> Self {
+        self.setup.push(Box::new(move |world: &mut World| {
+            if !world.has_resource::<T>() {
+                world.insert_resource(resource.clone());
+            }
+        }));
+        self
+    }
+
+    /// Registers an event type in every scene the engine runs
+    pub fn add_event<T: 'static>(mut self) -> Self {
+        self.setup.push(Box::new(|world: &mut World| world.add_event::<T>()));
+        self
+    }
+
+    pub fn build(self) -> Result<(Engine, EventLoop<()>), ScheduleError> {
+        let (renderer, event_loop) = Renderer::init(&self.title, self.size.0, self.size.1);
+        let engine = self.build_with(Some(renderer))?;
+
+        Ok((engine, event_loop))
+    }
+
+    /// Builds an engine without a window, GPU or audio device, for tests and
+    /// dedicated servers. The render and audio systems are left out and the
+    /// render stage never runs, drive it with `Engine::run_frames`.
+    pub fn build_headless(self) -> Result<Engine, ScheduleError> {
+        self.build_with(None)
+    }
+
+    /// Builds an engine that renders into an image the size of the window
+    /// instead of a window, e.g. for golden-image tests. Like a headless
+    /// engine it has no event loop, drive it with `Engine::run_frames` and
+    /// request frames with the `CaptureFrame` event.
+    pub fn build_offscreen(self) -> Result<Engine, ScheduleError> {
+        let renderer = Renderer::offscreen(self.size.0, self.size.1);
+        self.build_with(Some(renderer))
+    }
+
+    fn build_with(self, mut renderer: Option<Renderer>) -> Result<Engine, ScheduleError> {
+        let headless = renderer.is_none();
+        let global = Global::new(
+            self.title.clone(),
+            self.size,
+            self.view_size.unwrap_or((self.size.0 as f32, self.size.1 as f32)),
+        );
+
+        let input = InputSystem::new(global.clone());
+
+        // Built-in systems keep their usual order regardless of when they were enabled
+        let mut systems = vec![];
+        for builtin in BuiltinSystem::ALL.iter().filter(|s| self.builtins.contains(s)) {
+            let descriptor = match builtin {
+                BuiltinSystem::Render => match renderer.take() {
+                    Some(renderer) => SystemDescriptor::new(RenderSystem::new(renderer, global.clone()))
+                        .in_stage(Stage::Render),
+                    None => continue,
+                },
+                BuiltinSystem::Audio if headless => continue,
+                BuiltinSystem::Audio => match AudioSystem::try_new() {
+                    Ok(audio) => SystemDescriptor::new(audio)
+                        .in_stage(Stage::PostPhysics),
+                    Err(e) => {
+                        println!("Running without audio: {}", e);
+                        continue;
+                    },
+                },
+                BuiltinSystem::Physics => SystemDescriptor::new(PhysicsSystem::new(global.clone()))
+                    .in_stage(Stage::Physics),
+                BuiltinSystem::Movement => SystemDescriptor::new(MovementSystem::new())
+                    .in_stage(Stage::Update),
+                BuiltinSystem::Collision => SystemDescriptor::new(CollisionSystem::new())
+                    .in_stage(Stage::Physics)
+                    .after::<PhysicsSystem>()
+                    .after::<TransformSystem>(),
+                BuiltinSystem::Transform => SystemDescriptor::new(TransformSystem::new())
+                    .in_stage(Stage::Physics)
+                    .after::<PhysicsSystem>(),
+                BuiltinSystem::Camera => SystemDescriptor::new(CameraSystem::new(global.clone()))
+                    .in_stage(Stage::PostPhysics)
+                    .after::<TransformSystem>(),
+                BuiltinSystem::Animation => SystemDescriptor::new(AnimationSystem::new())
+                    .in_stage(Stage::Update),
+            };
+            systems.push(descriptor);
+        }
+
+        for edit in self.edits {
+            match edit {
+                SystemEdit::Add(descriptor) => systems.push(descriptor),
+                SystemEdit::Remove(name) => systems.retain(|d| d.name() != name),
+                SystemEdit::Replace(name, descriptor) => replace_system(&mut systems, name, descriptor),
+            }
+        }
+        let schedule = Schedule::new(systems)?;
+
+        let mut scenes = SceneStack::new(self.scene);
+        scenes.options = self.options;
+        for setup in self.setup {
+            scenes.on_enter(setup);
+        }
+
+        Ok(Engine {
+            global,
+            scenes,
+            fps: self.fps,
+            timestep: FixedTimestep::new(self.fixed_step),
+            last_frame: None,
+            schedule,
+            input,
+            headless,
+        })
+    }
+}
+
+fn replace_system(systems: &mut [SystemDescriptor], name: &str, replacement: SystemDescriptor) {
+    let i = match systems.iter().position(|d| d.name() == name) {
+        Some(i) => i,
+        None => return,
+    };
+
+    systems[i].system = replacement.system;
+    systems[i].name = replacement.name.clone();
+
+    // Constraints on the old system now apply to its replacement
+    for descriptor in systems.iter_mut() {
+        for other in descriptor.before.iter_mut().chain(descriptor.after.iter_mut()) {
+            if other == name {
+                *other = replacement.name.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventReader;
+    use crate::scene::stack::Transition;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Score(u32);
+
+    struct Ping;
+
+    #[test]
+    #[should_panic(expected = "tick rate")]
+    fn rejects_a_tick_rate_of_zero() {
+        let _ = EngineBuilder::new("test", 320, 240).tick_rate(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "view size")]
+    fn rejects_an_empty_view() {
+        let _ = EngineBuilder::new("test", 320, 240).view_size(320.0, 0.0);
+    }
+
+    #[test]
+    fn sets_up_scenes_entering_the_stack() {
+        let mut engine = EngineBuilder::new("test", 320, 240)
+            .insert_resource(Score(3))
+            .add_event::<Ping>()
+            .build_headless()
+            .unwrap();
+
+        let transition = Transition::Push { scene: Scene::new(vec![]).into(), overlay: false };
+        engine.scenes.apply(transition).unwrap();
+        assert_eq!(engine.scenes.len(), 2);
+
+        let world = engine.world().unwrap();
+        assert_eq!(world.resource::<Score>().as_deref(), Some(&Score(3)));
+        world.send_event(Ping);
+        assert_eq!(EventReader::<Ping>::new().read(&world.events::<Ping>()).count(), 1);
+    }
+}
@@ -1,61 +1,131 @@
 use crate::components::Component;
+use crate::components::animation::{check_frame_names, AnimationComponent};
 use crate::components::audio::AudioComponent;
+use crate::components::camera::CameraComponent;
 use crate::components::moveable::MoveableComponent;
 use crate::components::position::PositionComponent;
 use crate::components::sprite::SpriteComponent;
 use crate::components::physics::PhysicsComponent;
 use crate::components::collision::CollisionComponent;
-use std::sync::{Arc, Mutex};
+use crate::components::custom::ComponentParser;
+use crate::xml::{write_element, LoadOptions, SceneError};
 
-#[derive(PartialEq)]
+/// The components the loader reads from elements under an `<entity>`
+/// itself, by element name
+const BUILTIN_COMPONENTS: &[(&str, ComponentParser)] = &[
+    ("animation", AnimationComponent::from_xml),
+    ("audio", AudioComponent::from_xml),
+    ("camera", CameraComponent::from_xml),
+    ("position", PositionComponent::from_xml),
+    ("sprite", SpriteComponent::from_xml),
+    ("physics", PhysicsComponent::from_xml),
+    ("moveable", MoveableComponent::from_xml),
+    ("collision", CollisionComponent::from_xml),
+];
+
+/// Whether the loader handles an element under an `<entity>` itself, as a
+/// built-in component or a child entity, so custom components can't use it
+pub fn is_builtin_element(tag: &str) -> bool {
+    tag == "entity" || builtin_parser(tag).is_some()
+}
+
+fn builtin_parser(tag: &str) -> Option<ComponentParser> {
+    BUILTIN_COMPONENTS.iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, parse)| *parse)
+}
+
+/// The human-readable name of an entity, taken from the `id` attribute in
+/// scene XML. Entities are identified by their `EntityId` once spawned.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Name(pub String);
+
+/// An entity's components before it is spawned into a `World`.
+///
+/// Children are spawned along with the entity, with their positions relative
+/// to it.
+#[derive(Clone, PartialEq)]
 pub struct Entity {
-    pub id: u32,
+    pub name: Option<String>,
     pub components: Vec<Component>,
+    pub children: Vec<Entity>,
 }
 
 impl Entity {
-    pub fn new(id: u32, components: Vec<Component>) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(
-            Entity {
-                id,
-                components,
-            }
-        ))
+    pub fn new(components: Vec<Component>) -> Self {
+        Entity {
+            name: None,
+            components,
+            children: vec![],
+        }
+    }
+
+    pub fn named(name: &str, components: Vec<Component>) -> Self {
+        Entity {
+            name: Some(name.to_string()),
+            components,
+            children: vec![],
+        }
     }
 
     pub fn add_component(&mut self, component: Component) {
         self.components.push(component);
     }
 
-    pub fn from_xml(data: roxmltree::Node) -> Arc<Mutex<Self>> {
-        let mut id: u32 = 0;
+    pub fn add_child(&mut self, child: Entity) {
+        self.children.push(child);
+    }
+
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Self, SceneError> {
+        let mut name = None;
         let mut components = vec![];
+        let mut children = vec![];
+
+        for a in data.attributes() {
+            match a.name() {
+                "id" => name = Some(String::from(a.value())),
+                _ => options.unknown_attribute(data, &a)?,
+            }
+        }
 
-        data.children()
-            .filter(|n| n.is_element())
-            .map(|n| {
-                match n.attribute("id") {
-                    Some(i) => id = i.as_bytes().iter().sum::<u8>() as u32,
-                    None => (),
-                }
-
-                match n.tag_name().name() {
-                    "audio" => components.push(AudioComponent::from_xml(n)),
-                    "position" => components.push(PositionComponent::from_xml(n)),
-                    "sprite" => components.push(SpriteComponent::from_xml(n)),
-                    "physics" => components.push(PhysicsComponent::from_xml(n)),
-                    "moveable" => components.push(MoveableComponent::new()),
-                    "collision" => components.push(CollisionComponent::new()),
-                    _ => (),
-                }
+        for n in data.children().filter(|n| n.is_element()) {
+            match n.tag_name().name() {
+                "entity" => children.push(Entity::from_xml(n, options)?),
+                tag => match builtin_parser(tag).or_else(|| options.parser(tag)) {
+                    Some(parse) => components.push(parse(n, options)?),
+                    None => options.unknown_element(n)?,
+                },
             }
-        ).for_each(drop);
+        }
 
-        Arc::new(Mutex::new(
-            Entity {
-                id,
-                components,
+        if options.strict {
+            let atlas = components.iter().find_map(|c| match c {
+                Component::Sprite(sprite) => sprite.atlas.as_ref().map(|(atlas, _)| atlas),
+                _ => None,
+            });
+            for n in data.children().filter(|n| n.has_tag_name("animation")) {
+                check_frame_names(n, atlas)?;
             }
-        ))
+        }
+
+        Ok(Entity {
+            name,
+            components,
+            children,
+        })
     }
-}
\ No newline at end of file
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = vec![];
+        if let Some(name) = &self.name {
+            attributes.push(("id", name.clone()));
+        }
+
+        let children: Vec<String> = self.components.iter()
+            .map(|c| c.to_xml())
+            .chain(self.children.iter().map(|e| e.to_xml()))
+            .collect();
+
+        write_element("entity", &attributes, &children)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::capture::Image;
+use crate::world::EntityId;
+
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers every event sent in any world, so a reader that moves between
+/// scenes never mistakes old events in one for new events in another
+static SEQUENCE: AtomicUsize = AtomicUsize::new(1);
+
+/// A double-buffered queue of events of one type.
+///
+/// Events stay readable for the frame they were sent in and the one after,
+/// which lets systems that run before the sender still see them. Readers that
+/// fall further behind miss them.
+pub struct Events<T> {
+    previous: Vec<(usize, T)>,
+    current: Vec<(usize, T)>,
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Events {
+            previous: vec![],
+            current: vec![],
+        }
+    }
+
+    pub fn send(&mut self, event: T) {
+        let id = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        self.current.push((id, event));
+    }
+
+    /// Drops the oldest buffer, called by the engine once per frame
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.current.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.update();
+        self.update();
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Events::new()
+    }
+}
+
+/// Tracks which events a system has already seen
+pub struct EventReader<T> {
+    last: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> EventReader<T> {
+    pub fn new() -> Self {
+        EventReader {
+            last: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the events sent since this reader last read
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let last = self.last;
+        if let Some((id, _)) = events.current.last().or_else(|| events.previous.last()) {
+            self.last = self.last.max(*id);
+        }
+
+        events.previous.iter()
+            .chain(events.current.iter())
+            .filter(move |(id, _)| *id > last)
+            .map(|(_, event)| event)
+    }
+
+    /// Marks every pending event as read, returning whether there were any
+    pub fn drain(&mut self, events: &Events<T>) -> bool {
+        self.read(events).count() > 0
+    }
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        EventReader::new()
+    }
+}
+
+/// The window was resized to the given physical size
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Resized {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The mouse was clicked inside the view, at a position in world units
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Clicked {
+    pub position: (f32, f32),
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub pressed: bool,
+}
+
+/// Starts the background music of every entity with an AudioComponent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlayBgm;
+
+/// Plays the sound effect of every entity with an AudioComponent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlaySfx;
+
+/// Entity `a` ran into entity `b` and was pushed back out of it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Collision {
+    pub a: EntityId,
+    pub b: EntityId,
+}
+
+/// An entity's animation clip reached its end, or completed a cycle if it
+/// repeats
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationFinished {
+    pub entity: EntityId,
+    pub clip: String,
+}
+
+/// Asks the renderer to read back the next frame it draws, sending it as
+/// `FrameCaptured` and saving it as a PNG if a path is given
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CaptureFrame {
+    pub path: Option<PathBuf>,
+}
+
+/// A frame read back after a `CaptureFrame` request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameCaptured {
+    pub image: Image,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_last_until_the_second_update() {
+        let mut events = Events::new();
+        let mut early = EventReader::new();
+        events.send(1);
+
+        assert_eq!(early.read(&events).collect::<Vec<_>>(), [&1]);
+
+        events.update();
+        events.send(2);
+        let mut late = EventReader::new();
+        assert_eq!(early.read(&events).collect::<Vec<_>>(), [&2]);
+        assert_eq!(late.read(&events).collect::<Vec<_>>(), [&1, &2]);
+
+        events.update();
+        let mut latest = EventReader::new();
+        assert_eq!(latest.read(&events).collect::<Vec<_>>(), [&2]);
+
+        events.update();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn readers_see_each_event_once() {
+        let mut events = Events::new();
+        let mut reader = EventReader::new();
+        events.send("a");
+
+        assert!(reader.drain(&events));
+        assert!(!reader.drain(&events));
+        events.update();
+        assert!(!reader.drain(&events));
+    }
+}
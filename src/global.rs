@@ -1,28 +1,43 @@
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 pub struct Global {
     pub title: String,
     pub window_size: (u32, u32),
+    /// The virtual resolution, how many world units fit across and down the
+    /// view at zoom 1 whatever the size of the window
     pub view_size: (f32, f32),
-    pub view_origin: (f32, f32),
+    /// Where the mouse was last clicked, in world units
     pub click: (f32, f32),
-    pub entity_map: HashMap<String, u32>,
-    pub signals: HashMap<String, bool>,
 }
 
 impl Global {
-    pub fn new(title: String, window_size: (u32, u32)) -> Arc<Mutex<Self>> {
+    pub fn new(title: String, window_size: (u32, u32), view_size: (f32, f32)) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(
             Global {
             title,
             window_size,
-            view_size: (window_size.0 as f32, window_size.1 as f32),
-            view_origin: (0.0, 0.0),
+            view_size,
             click: (0.0, 0.0),
-            entity_map: HashMap::new(),
-            signals: HashMap::new(),
             }
         ))
     }
+
+    /// The part of the window the view is drawn to, as its origin and size
+    /// in pixels. The view keeps the aspect ratio of `view_size`, leaving
+    /// bars along two edges of the window if the window's differs.
+    pub fn viewport(&self) -> ((f32, f32), (f32, f32)) {
+        let x = self.window_size.0 as f32;
+        let y = self.window_size.1 as f32;
+
+        let res_ratio = self.view_size.0 / self.view_size.1;
+        let win_ratio = x / y;
+
+        if win_ratio > res_ratio {
+            let vx = y * res_ratio;
+            ((x / 2.0 - vx / 2.0, 0.0), (vx, y))
+        } else {
+            let vy = x / res_ratio;
+            ((0.0, y / 2.0 - vy / 2.0), (x, vy))
+        }
+    }
 }
\ No newline at end of file
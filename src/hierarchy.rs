@@ -0,0 +1,20 @@
+use crate::world::EntityId;
+
+/// Attaches an entity to a parent, whose position its own is relative to.
+///
+/// `offset` is the entity's position in its parent's space. The
+/// `TransformSystem` adds it to the parent's position every step to get the
+/// child's `PositionComponent::position`.
+///
+/// Children are kinematic: anything else that moves them, such as their own
+/// physics or being pushed out of a collision, is overwritten on the next
+/// step. Change `offset` to move a child relative to its parent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parent {
+    pub entity: EntityId,
+    pub offset: (f32, f32),
+}
+
+/// The entities attached to this one, kept in sync with their `Parent`s
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Children(pub Vec<EntityId>);
@@ -0,0 +1,83 @@
+use crate::xml::{write_element, LoadOptions, SceneError};
+
+/// The named layers a scene's sprites are drawn in, from the back to the
+/// front. A resource in every scene's world.
+///
+/// Sprites are drawn layer by layer, and by their `z` within a layer.
+/// Sprites in a layer the scene doesn't have are drawn in front of all
+/// of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layers {
+    names: Vec<String>,
+}
+
+impl Layers {
+    pub const DEFAULT: [&'static str; 4] = ["background", "world", "foreground", "ui"];
+
+    /// The layer sprites are in unless they say otherwise
+    pub const DEFAULT_LAYER: &'static str = "world";
+
+    pub fn new<S: ToString>(names: &[S]) -> Self {
+        Layers {
+            names: names.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Where a layer is drawn, 0 being the back
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Reads `<layers>` with a `<layer name="..."/>` for each layer, back to
+    /// front
+    pub fn from_xml(data: roxmltree::Node, options: &LoadOptions) -> Result<Self, SceneError> {
+        for a in data.attributes() {
+            options.unknown_attribute(data, &a)?;
+        }
+
+        let mut names = vec![];
+        for n in data.children().filter(|n| n.is_element()) {
+            match n.tag_name().name() {
+                "layer" => {
+                    let mut name = None;
+                    for a in n.attributes() {
+                        match a.name() {
+                            "name" => name = Some(a.value()),
+                            _ => options.unknown_attribute(n, &a)?,
+                        }
+                    }
+                    names.push(name.ok_or_else(|| SceneError::missing_attribute(n, "name"))?);
+                },
+                _ => options.unknown_element(n)?,
+            }
+        }
+
+        Ok(Layers::new(&names))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let layers: Vec<String> = self.names.iter()
+            .map(|n| write_element("layer", &[("name", n.clone())], &[]))
+            .collect();
+
+        write_element("layers", &[], &layers)
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers::new(&Layers::DEFAULT)
+    }
+}
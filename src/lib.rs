@@ -1,9 +1,20 @@
+pub mod atlas;
+pub mod capture;
+pub mod commands;
 pub mod engine;
 pub mod global;
 pub mod scene;
 pub mod entity;
+pub mod events;
+pub mod hierarchy;
+pub mod layers;
+pub mod world;
+pub mod xml;
 pub mod components;
+pub mod schedule;
 pub mod systems;
+pub mod texture;
+pub mod time;
 mod renderer;
 mod shaders;
 mod vertex;
@@ -12,7 +23,6 @@ use engine::Engine;
 use scene::Scene;
 
 use std::ffi::CStr;
-use std::fs;
 use std::os::raw::c_char;
 
 #[no_mangle]
@@ -40,11 +50,13 @@ pub extern "C" fn init_with_scene(title: *const c_char, width: u32, height: u32,
             .to_str()
             .expect("Failed to decode title")
     };
-    let scene = fs::read_to_string(scene).expect("Failed to find scene file");
-    let scene = Scene::from_xml(&scene);
+    let scene = Scene::from_file(scene)
+        .unwrap_or_else(|e| panic!("Failed to load scene: {}", e));
 
-    let (mut engine, event_loop) = Engine::new(title, width, height);
-    engine.scene = scene;
+    let (engine, event_loop) = Engine::builder(title, width, height)
+        .scene(scene)
+        .build()
+        .expect("Failed to schedule systems");
 
     engine.run(event_loop);
 }
\ No newline at end of file
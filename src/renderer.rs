@@ -1,15 +1,16 @@
+use crate::capture::Image;
 use crate::shaders;
 use crate::vertex::Vertex;
 
-use std::io::Cursor;
 use std::sync::Arc;
 use std::collections::HashMap;
 
 use vulkano::command_buffer::{CommandBufferExecFuture, PrimaryAutoCommandBuffer};
 use vulkano::format::Format;
-use vulkano::instance::Instance;
+use vulkano::instance::{Instance, InstanceExtensions};
 use vulkano::device::{
     Device,
+    DeviceExtensions,
     Features,
     QueuesIter,
 };
@@ -18,7 +19,7 @@ use vulkano::device::physical::{
     PhysicalDeviceType,
 };
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
-use vulkano::image::{ImageUsage, ImageDimensions, MipmapsCount, ImmutableImage};
+use vulkano::image::{AttachmentImage, ImageUsage, ImageDimensions, MipmapsCount, ImmutableImage};
 use vulkano::pipeline::graphics::color_blend::ColorBlendState;
 use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
 use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
@@ -37,13 +38,58 @@ use winit::event_loop::EventLoop;
 use winit::window::WindowBuilder;
 
 use vulkano::image::{ImageAccess, SwapchainImage};
-use vulkano::image::view::ImageView;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
 use vulkano::render_pass::{Framebuffer, RenderPass};
 use winit::window::Window;
 
+/// Where the renderer draws to
+pub enum Target {
+    /// A window's swapchain, presented every frame
+    Window {
+        surface: Arc<Surface<Window>>,
+        swapchain: Arc<Swapchain<Window>>,
+        images: Vec<Arc<SwapchainImage<Window>>>,
+    },
+    /// A single image that is only ever read back, for rendering without a
+    /// window
+    Offscreen(Arc<AttachmentImage>),
+}
+
+impl Target {
+    pub fn format(&self) -> Format {
+        match self {
+            Target::Window { swapchain, .. } => swapchain.format(),
+            Target::Offscreen(image) => image.format(),
+        }
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        match self {
+            Target::Window { images, .. } => images[0].dimensions().width_height(),
+            Target::Offscreen(image) => image.dimensions().width_height(),
+        }
+    }
+
+    /// The image drawn to for the given swapchain image number
+    pub fn image(&self, image_num: usize) -> Arc<dyn ImageAccess> {
+        match self {
+            Target::Window { images, .. } => images[image_num].clone(),
+            Target::Offscreen(image) => image.clone(),
+        }
+    }
+
+    fn views(&self) -> Vec<Arc<dyn ImageViewAbstract>> {
+        match self {
+            Target::Window { images, .. } => images.iter()
+                .map(|image| ImageView::new(image.clone()).unwrap() as Arc<dyn ImageViewAbstract>)
+                .collect(),
+            Target::Offscreen(image) => vec![ImageView::new(image.clone()).unwrap()],
+        }
+    }
+}
+
 pub struct Renderer {
-    pub surface: Arc<Surface<Window>>,
-    pub swapchain: Arc<Swapchain<Window>>,
+    pub target: Target,
     pub sampler: Arc<Sampler>,
     pub framebuffers: Vec<Arc<Framebuffer>>,
     pub viewport: Viewport,
@@ -54,11 +100,11 @@ pub struct Renderer {
 }
 
 impl Renderer {
-    fn get_instance() -> Arc<Instance> {
+    fn get_instance(extensions: &InstanceExtensions) -> Arc<Instance> {
         let instance = Instance::new(
             None,
             Version::V1_1,
-            &vulkano_win::required_extensions(),
+            extensions,
             None,
         ).expect("Failed to create instance");
 
@@ -74,12 +120,7 @@ impl Renderer {
         surface
     }
 
-    fn get_device_and_queues(instance: &Arc<Instance>) -> (Arc<Device>, QueuesIter) {
-        let device_ext = vulkano::device::DeviceExtensions {
-            khr_swapchain: true,
-            .. vulkano::device::DeviceExtensions::none()
-        };
-
+    fn get_device_and_queues(instance: &Arc<Instance>, device_ext: DeviceExtensions) -> (Arc<Device>, QueuesIter) {
         let (physical, queue_family) = PhysicalDevice::enumerate(instance)
             .filter(|&p| p.supported_extensions().is_superset_of(&device_ext))
             .filter_map(|p| {
@@ -116,11 +157,17 @@ impl Renderer {
         let alpha = caps.supported_composite_alpha.iter().next().unwrap();
         let format = caps.supported_formats[0].0;
 
+        // Copying out of the swapchain is only needed for screenshots
+        let usage = ImageUsage {
+            transfer_source: caps.supported_usage_flags.transfer_source,
+            .. ImageUsage::color_attachment()
+        };
+
         let (swapchain, images) = Swapchain::start(device.clone(), surface.clone())
             .num_images(caps.min_image_count)
             .format(format)
             .dimensions(dims)
-            .usage(ImageUsage::color_attachment())
+            .usage(usage)
             .sharing_mode(queue)
             .composite_alpha(alpha)
             .build()
@@ -129,50 +176,24 @@ impl Renderer {
         (swapchain, images)
     }
 
-    pub fn create_vertex_buffer(vertices: Vec<Vertex>, device: &Arc<Device>) -> Arc<CpuAccessibleBuffer<[Vertex]>> {
-        let vertex_buffer = CpuAccessibleBuffer::from_iter(
-            device.clone(),
-            BufferUsage::all(),
-            false,
-            vertices.into_iter(),
-        ).expect("Failed to create buffer");
-
-        vertex_buffer
-    }
-
-    pub fn create_texture(&self, data : &[u8]) -> (Arc<ImageView<ImmutableImage>>, CommandBufferExecFuture<NowFuture, PrimaryAutoCommandBuffer>) {
-        let (texture, tex_future) = {
-            let cursor = Cursor::new(data);
-            let decoder = png::Decoder::new(cursor);
-            let mut reader = decoder.read_info().unwrap();
-            let info = reader.info();
-            let dims = ImageDimensions::Dim2d {
-                width: info.width,
-                height: info.height,
-                array_layers: 1,
-            };
-
-            let mut image_data = Vec::new();
-            let depth: u32 = match info.bit_depth {
-                png::BitDepth::One => 1,
-                png::BitDepth::Two => 2,
-                png::BitDepth::Four => 4,
-                png::BitDepth::Eight => 8,
-                png::BitDepth::Sixteen => 16,
-            };
-            image_data.resize((info.width * info.height * depth) as usize, 0);
-            reader.next_frame(&mut image_data).unwrap();
-            let (image, future) = ImmutableImage::from_iter(
-                image_data.iter().cloned(),
-                dims,
-                MipmapsCount::One,
-                Format::R8G8B8A8_SRGB,
-                self.queue.clone()
-            ).unwrap();
-            (ImageView::new(image).unwrap(), future)
+    /// Uploads an RGBA8 image, returning it with the future that completes
+    /// the upload
+    pub fn create_texture(&self, image: &Image) -> (Arc<ImageView<ImmutableImage>>, CommandBufferExecFuture<NowFuture, PrimaryAutoCommandBuffer>) {
+        let dims = ImageDimensions::Dim2d {
+            width: image.width,
+            height: image.height,
+            array_layers: 1,
         };
 
-        (texture, tex_future)
+        let (texture, tex_future) = ImmutableImage::from_iter(
+            image.data.iter().cloned(),
+            dims,
+            MipmapsCount::One,
+            Format::R8G8B8A8_SRGB,
+            self.queue.clone()
+        ).unwrap();
+
+        (ImageView::new(texture).unwrap(), tex_future)
     }
 
     pub fn create_sampler(&self) -> Arc<Sampler> {
@@ -187,18 +208,26 @@ impl Renderer {
         sampler
     }
 
+    /// Rebuilds the swapchain at the window's current size. Offscreen
+    /// targets never change size.
     pub fn recreate_swapchain(&mut self) -> Result<(), ()> {
-        let dims: [u32; 2] = self.surface.window().inner_size().into();
-        let (swapchain, images) =
-            match self.swapchain.recreate().dimensions(dims).build() {
+        let (surface, swapchain, images) = match &mut self.target {
+            Target::Window { surface, swapchain, images } => (surface, swapchain, images),
+            Target::Offscreen(_) => return Ok(()),
+        };
+
+        let dims: [u32; 2] = surface.window().inner_size().into();
+        let (new_swapchain, new_images) =
+            match swapchain.recreate().dimensions(dims).build() {
                 Ok(r) => r,
                 Err(SwapchainCreationError::UnsupportedDimensions) => return Err(()),
                 Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
             };
 
-        self.swapchain = swapchain;
-        self.framebuffers = Renderer::window_size_dependent_setup(
-            &images,
+        *swapchain = new_swapchain;
+        *images = new_images;
+        self.framebuffers = Renderer::target_dependent_setup(
+            &self.target,
             self.render_pass.clone(),
             &mut self.viewport,
         );
@@ -207,30 +236,41 @@ impl Renderer {
     }
 
     pub fn recreate_pipelines(&mut self) -> Result<(), ()> {
-        let viewport = self.viewport.clone();
-
         for (shader, pipeline) in self.pipelines.iter_mut() {
-            let shader = shaders::get_shaders(&shader, &self.device);
-            let subpass = Subpass::from(self.render_pass.clone(), 0).unwrap();
-
-            *pipeline = GraphicsPipeline::start()
-                .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-                .vertex_shader(shader[0].entry_point("main").unwrap(), ())
-                .input_assembly_state(InputAssemblyState::new())
-                .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport.clone()]))
-                .fragment_shader(shader[1].entry_point("main").unwrap(), ())
-                .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
-                .render_pass(subpass)
-                .build(self.device.clone())
-                .unwrap();
+            *pipeline = Renderer::create_pipeline(*shader, &self.device, &self.render_pass, &self.viewport);
         }
 
         Ok(())
     }
 
-    pub fn acquire_next_image(&self) -> Result<(usize, bool, SwapchainAcquireFuture<Window>), ()> {
+    /// Builds the pipeline drawing sprites with a shader. Every pipeline
+    /// blends by alpha, so sprites are drawn over what's below them.
+    fn create_pipeline(shader: shaders::Shader, device: &Arc<Device>, render_pass: &Arc<RenderPass>, viewport: &Viewport) -> Arc<GraphicsPipeline> {
+        let s = shaders::get_shaders(&shader, device);
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_shader(s[0].entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport.clone()]))
+            .fragment_shader(s[1].entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+            .render_pass(subpass)
+            .build(device.clone())
+            .unwrap()
+    }
+
+    /// Picks the image to draw the next frame to. Offscreen targets only
+    /// have the one, which is ready straight away.
+    pub fn acquire_next_image(&self) -> Result<(usize, bool, Option<SwapchainAcquireFuture<Window>>), ()> {
+        let swapchain = match &self.target {
+            Target::Window { swapchain, .. } => swapchain,
+            Target::Offscreen(_) => return Ok((0, false, None)),
+        };
+
         let (image_num, suboptimal, acquire_future) =
-            match swapchain::acquire_next_image(self.swapchain.clone(), None) {
+            match swapchain::acquire_next_image(swapchain.clone(), None) {
                 Ok(r) => r,
                 Err(AcquireError::OutOfDate) => {
                     return Err(());
@@ -238,20 +278,52 @@ impl Renderer {
                 Err(e) => panic!("Failed to acquire next image: {:?}", e),
             };
 
-        Ok((image_num, suboptimal, acquire_future))
+        Ok((image_num, suboptimal, Some(acquire_future)))
     }
 
     pub fn init(title: &str, width: u32, height: u32) -> (Self, EventLoop<()>) {
-        let instance = Renderer::get_instance();
+        let instance = Renderer::get_instance(&vulkano_win::required_extensions());
         let event_loop = EventLoop::new();
         let surface = Renderer::get_surface(&event_loop, &instance, title);
 
-        let (device, mut queues) = Renderer::get_device_and_queues(&instance);
+        let device_ext = DeviceExtensions {
+            khr_swapchain: true,
+            .. DeviceExtensions::none()
+        };
+        let (device, mut queues) = Renderer::get_device_and_queues(&instance, device_ext);
         let queue = queues.next()
             .expect("Could not select queue");
 
         let (swapchain, images) = Renderer::get_swapchain(&surface, &device, &queue, &width, &height);
+        let target = Target::Window {
+            surface,
+            swapchain,
+            images,
+        };
+
+        (Renderer::new(target, device, queue, width, height), event_loop)
+    }
+
+    /// Creates a renderer that draws to an image instead of a window, which
+    /// works without a display, e.g. under a software driver such as lavapipe
+    pub fn offscreen(width: u32, height: u32) -> Self {
+        let instance = Renderer::get_instance(&InstanceExtensions::none());
 
+        let (device, mut queues) = Renderer::get_device_and_queues(&instance, DeviceExtensions::none());
+        let queue = queues.next()
+            .expect("Could not select queue");
+
+        let usage = ImageUsage {
+            transfer_source: true,
+            .. ImageUsage::color_attachment()
+        };
+        let image = AttachmentImage::with_usage(device.clone(), [width, height], Format::R8G8B8A8_SRGB, usage)
+            .expect("Failed to create render target");
+
+        Renderer::new(Target::Offscreen(image), device, queue, width, height)
+    }
+
+    fn new(target: Target, device: Arc<Device>, queue: Arc<Queue>, width: u32, height: u32) -> Self {
         vulkano::impl_vertex!(Vertex, position, color, uv);
 
         let render_pass = vulkano::single_pass_renderpass!(
@@ -260,7 +332,7 @@ impl Renderer {
                 color: {
                     load: Clear,
                     store: Store,
-                    format: swapchain.format(),
+                    format: target.format(),
                     samples: 1,
                 }
             },
@@ -291,20 +363,8 @@ impl Renderer {
         ];
 
         let mut pipelines = HashMap::new();
-        for shader in shaders.iter() {
-            let s = shaders::get_shaders(shader, &device);
-            pipelines.insert(
-                shader.clone(),
-                GraphicsPipeline::start()
-                    .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-                    .vertex_shader(s[0].entry_point("main").unwrap(), ())
-                    .input_assembly_state(InputAssemblyState::new())
-                    .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport.clone()]))
-                    .fragment_shader(s[1].entry_point("main").unwrap(), ())
-                    .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-                    .build(device.clone())
-                    .unwrap(),
-            );
+        for shader in shaders {
+            pipelines.insert(shader, Renderer::create_pipeline(shader, &device, &render_pass, &viewport));
         }
 
         let mut viewport = Viewport {
@@ -312,11 +372,10 @@ impl Renderer {
             dimensions: [0.0, 0.0],
             depth_range: 0.0..1.0,
         };
-        let framebuffers = Renderer::window_size_dependent_setup(&images, render_pass.clone(), &mut viewport);
+        let framebuffers = Renderer::target_dependent_setup(&target, render_pass.clone(), &mut viewport);
 
-        (Renderer {
-            surface,
-            swapchain,
+        Renderer {
+            target,
             sampler,
             framebuffers,
             viewport,
@@ -324,21 +383,20 @@ impl Renderer {
             device,
             queue,
             pipelines,
-        }, event_loop)
+        }
     }
 
-    pub fn window_size_dependent_setup(
-        images: &[Arc<SwapchainImage<Window>>],
+    pub fn target_dependent_setup(
+        target: &Target,
         render_pass: Arc<RenderPass>,
         viewport: &mut Viewport,
     ) -> Vec<Arc<Framebuffer>> {
-        let dimensions = images[0].dimensions().width_height();
+        let dimensions = target.dimensions();
         viewport.dimensions = [dimensions[0] as f32, dimensions[1] as f32];
 
-        images
-            .iter()
-            .map(|image| {
-                let view = ImageView::new(image.clone()).unwrap();
+        target.views()
+            .into_iter()
+            .map(|view| {
                 Framebuffer::start(render_pass.clone())
                     .add(view)
                     .unwrap()
@@ -347,4 +405,39 @@ impl Renderer {
             })
             .collect::<Vec<_>>()
     }
+
+    /// A buffer the target image can be copied into, see `read_back`
+    pub fn create_capture_buffer(&self) -> Arc<CpuAccessibleBuffer<[u8]>> {
+        let [width, height] = self.target.dimensions();
+
+        CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_destination(),
+            true,
+            (0..width * height * 4).map(|_| 0u8),
+        ).expect("Failed to create buffer")
+    }
+
+    /// Converts a copy of the target image to RGBA, once the GPU has finished
+    /// writing it. Returns `None` for formats that can't be converted.
+    pub fn read_back(&self, buffer: &CpuAccessibleBuffer<[u8]>) -> Option<Image> {
+        let [width, height] = self.target.dimensions();
+        let mut data = buffer.read().ok()?.to_vec();
+
+        match self.target.format() {
+            Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => (),
+            Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => {
+                for pixel in data.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            },
+            _ => return None,
+        }
+
+        Some(Image {
+            width,
+            height,
+            data,
+        })
+    }
 }
@@ -1,47 +1,220 @@
+pub mod stack;
+
+use crate::commands::Commands;
+use crate::scene::stack::Transitions;
 use crate::entity::Entity;
-use crate::systems::System;
+use crate::events::{AnimationFinished, CaptureFrame, Clicked, Collision, FrameCaptured, KeyEvent, PlayBgm, PlaySfx, Resized};
+use crate::time::Time;
+use crate::world::{EntityId, World};
+use crate::hierarchy::Parent;
+use crate::layers::Layers;
+use crate::xml::{write_element, ErrorKind, LoadOptions, SceneError};
 
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
 
 pub struct Scene {
-    pub systems: Vec<Box<dyn System>>,
-    pub entities: Vec<Arc<Mutex<Entity>>>,
+    pub world: World,
 }
 
 impl Scene {
-    pub fn new(entities: Vec<Arc<Mutex<Entity>>>) -> Self {
-        Scene {
-            entities,
-            systems: vec![],
-        }
+    pub fn new(entities: Vec<Entity>) -> Self {
+        let mut scene = Scene {
+            world: World::new(),
+        };
+
+        scene.world.add_event::<Resized>();
+        scene.world.add_event::<Clicked>();
+        scene.world.add_event::<KeyEvent>();
+        scene.world.add_event::<PlayBgm>();
+        scene.world.add_event::<PlaySfx>();
+        scene.world.add_event::<Collision>();
+        scene.world.add_event::<CaptureFrame>();
+        scene.world.add_event::<FrameCaptured>();
+        scene.world.add_event::<AnimationFinished>();
+        scene.world.insert_resource(Time::new());
+        scene.world.insert_resource(Commands::new());
+        scene.world.insert_resource(Transitions::new());
+        scene.world.insert_resource(Layers::default());
+
+        for entity in entities { scene.add_entity(entity); }
+        scene
+    }
+
+    pub fn add_entity(&mut self, entity: Entity) -> EntityId {
+        self.world.spawn_entity(entity)
+    }
+
+    pub fn remove_entity(&mut self, entity: EntityId) -> bool {
+        self.world.despawn(entity)
     }
 
-    pub fn add_entity(&mut self, entity: Arc<Mutex<Entity>>) {
-        self.entities.push(entity);
+    /// Looks up an entity by the `id` it was given in the scene XML
+    pub fn find(&self, name: &str) -> Option<EntityId> {
+        self.world.find(name)
     }
 
-    pub fn add_system(&mut self, system: Box<dyn System>) {
-        self.systems.push(system);
+    pub fn from_xml(data: &str) -> Result<Self, SceneError> {
+        Scene::from_xml_with(data, &LoadOptions::default())
     }
 
-    pub fn from_xml(data: &str) -> Self {
-        let data = roxmltree::Document::parse(data).expect("Could not parse scene XML");
+    pub fn from_xml_with(data: &str, options: &LoadOptions) -> Result<Self, SceneError> {
+        let data = roxmltree::Document::parse(data)?;
+        let root = data.root_element();
+        if !root.has_tag_name("scene") {
+            options.unknown_element(root)?;
+        }
+        for a in root.attributes() {
+            options.unknown_attribute(root, &a)?;
+        }
+
+        check_ids(root, &mut HashSet::new())?;
 
         let mut entities = vec![];
+        let mut layers = None;
+
+        for n in root.children().filter(|n| n.is_element()) {
+            match n.tag_name().name() {
+                "entity" => entities.push(Entity::from_xml(n, options)?),
+                "layers" => layers = Some(Layers::from_xml(n, options)?),
+                _ => options.unknown_element(n)?,
+            }
+        }
+
+        let mut scene = Scene::new(entities);
+        if let Some(layers) = layers {
+            scene.world.insert_resource(layers);
+        }
+        Ok(scene)
+    }
+
+    /// Reads and loads a scene XML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        Scene::from_file_with(path, &LoadOptions::default())
+    }
+
+    pub fn from_file_with<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .map_err(|e| SceneError::file(&path.display().to_string(), e))?;
+
+        Scene::from_xml_with(&data, options)
+    }
+
+    /// Writes the scene back out in the format `from_xml` reads, including
+    /// runtime state such as velocities
+    pub fn to_xml(&self) -> String {
+        let mut children = vec![];
+        if let Some(layers) = self.world.resource::<Layers>() {
+            if *layers != Layers::default() {
+                children.push(layers.to_xml());
+            }
+        }
+
+        children.extend(self.world.entities()
+            .filter(|e| !self.world.has::<Parent>(*e))
+            .filter_map(|e| self.world.snapshot(e))
+            .map(|e| e.to_xml()));
+
+        write_element("scene", &[], &children)
+    }
+}
 
-        data.root_element().children()
-            .filter(|n| n.is_element())
-            .map(|n| {
-                match n.tag_name().name() {
-                    "entity" => entities.push(Entity::from_xml(n)),
-                    _ => (),
-                }
+/// Makes sure no two entities share an id, as they would otherwise take the
+/// name from each other when spawned
+fn check_ids<'a>(node: roxmltree::Node<'a, '_>, seen: &mut HashSet<&'a str>) -> Result<(), SceneError> {
+    for entity in node.children().filter(|n| n.has_tag_name("entity")) {
+        if let Some(id) = entity.attributes().find(|a| a.name() == "id") {
+            if !seen.insert(id.value()) {
+                return Err(SceneError::at_attribute(entity, &id, ErrorKind::Duplicate(id.value().to_string())));
             }
-        ).for_each(drop);
+        }
+        check_ids(entity, seen)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_error(xml: &str, options: &LoadOptions) -> SceneError {
+        match Scene::from_xml_with(xml, options) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn reports_where_values_are_invalid() {
+        let xml = "<scene>\n  <entity>\n    <position x=\"0\" y=\"oops\" width=\"1\" height=\"1\"/>\n  </entity>\n</scene>";
+        let error = load_error(xml, &LoadOptions::default());
+
+        assert!(matches!(&error.kind, ErrorKind::InvalidValue(v) if v == "oops"));
+        assert_eq!((error.line, error.column), (3, 21));
+        assert_eq!(error.to_string(), "3:21: <position y>: Invalid value \"oops\"");
+    }
+
+    #[test]
+    fn reports_missing_attributes_at_their_element() {
+        let xml = "<scene>\n  <entity><position x=\"0\" y=\"0\" width=\"1\"/></entity>\n</scene>";
+        let error = load_error(xml, &LoadOptions::default());
+
+        assert!(matches!(error.kind, ErrorKind::MissingAttribute));
+        assert_eq!((error.line, error.column), (2, 11));
+        assert_eq!(error.attribute.as_deref(), Some("height"));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let error = load_error("<scene>\n  <entity>\n</scene>", &LoadOptions::default());
+        assert!(matches!(error.kind, ErrorKind::Syntax(_)));
+        assert_eq!(error.line, 3);
+    }
+
+    #[test]
+    fn skips_unknown_names_unless_strict() {
+        let xml = "<scene>\n  <entity>\n    <position x=\"0\" y=\"0\" width=\"1\" height=\"1\" depth=\"2\"/>\n    <hat/>\n  </entity>\n</scene>";
+        assert!(Scene::from_xml(xml).is_ok());
+
+        let error = load_error(xml, &LoadOptions::strict());
+        assert!(matches!(error.kind, ErrorKind::UnknownAttribute));
+        assert_eq!(error.attribute.as_deref(), Some("depth"));
+
+        let xml = xml.replace(" depth=\"2\"", "");
+        let error = load_error(&xml, &LoadOptions::strict());
+        assert!(matches!(error.kind, ErrorKind::UnknownElement));
+        assert_eq!((error.line, error.column, error.element.as_deref()), (4, 5, Some("hat")));
+    }
 
-        Scene {
-            systems: vec![],
-            entities,
+    #[test]
+    fn checks_the_root_element_when_strict() {
+        for xml in ["<level/>", "<scene version=\"2\"/>"] {
+            assert!(Scene::from_xml(xml).is_ok());
+            assert!(Scene::from_xml_with(xml, &LoadOptions::strict()).is_err(), "{}", xml);
+        }
+
+        let error = load_error("<level/>", &LoadOptions::strict());
+        assert_eq!((error.line, error.column, error.element.as_deref()), (1, 1, Some("level")));
+    }
+
+    #[test]
+    fn rejects_numbers_that_arent_finite() {
+        for value in ["NaN", "inf", "-infinity"] {
+            let xml = format!("<scene><entity><position x=\"{}\" y=\"0\" width=\"1\" height=\"1\"/></entity></scene>", value);
+            let error = load_error(&xml, &LoadOptions::default());
+            assert!(matches!(error.kind, ErrorKind::InvalidValue(_)), "{}", error);
+            assert_eq!(error.attribute.as_deref(), Some("x"));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rejects_entities_sharing_an_id() {
+        let xml = "<scene>\n  <entity id=\"player\"/>\n  <entity>\n    <entity id=\"player\"/>\n  </entity>\n</scene>";
+        let error = load_error(xml, &LoadOptions::default());
+        assert!(matches!(error.kind, ErrorKind::Duplicate(_)));
+        assert_eq!((error.line, error.column), (4, 13));
+    }
+}
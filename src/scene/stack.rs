@@ -0,0 +1,344 @@
+use crate::events::PlayBgm;
+use crate::scene::Scene;
+use crate::world::World;
+use crate::xml::{LoadOptions, SceneError};
+
+use std::path::PathBuf;
+
+/// Where the scene for a transition comes from
+pub enum SceneSource {
+    Loaded(Box<Scene>),
+    /// A scene XML file, read when the transition is applied
+    File(PathBuf),
+}
+
+impl SceneSource {
+    pub fn load(self, options: &LoadOptions) -> Result<Scene, SceneError> {
+        match self {
+            SceneSource::Loaded(scene) => Ok(*scene),
+            SceneSource::File(path) => Scene::from_file_with(path, options),
+        }
+    }
+}
+
+impl From<Scene> for SceneSource {
+    fn from(scene: Scene) -> Self {
+        SceneSource::Loaded(Box::new(scene))
+    }
+}
+
+impl From<PathBuf> for SceneSource {
+    fn from(path: PathBuf) -> Self {
+        SceneSource::File(path)
+    }
+}
+
+impl From<&str> for SceneSource {
+    fn from(path: &str) -> Self {
+        SceneSource::File(PathBuf::from(path))
+    }
+}
+
+impl From<String> for SceneSource {
+    fn from(path: String) -> Self {
+        SceneSource::File(PathBuf::from(path))
+    }
+}
+
+pub enum Transition {
+    /// Pauses the active scene and runs `scene` on top of it. An overlay
+    /// leaves the paused scenes below it visible.
+    Push { scene: SceneSource, overlay: bool },
+    /// Drops the active scene and resumes the one below it
+    Pop,
+    /// Swaps the active scene for another
+    Replace(SceneSource),
+}
+
+/// Scene changes requested by systems, a resource in every scene's world.
+///
+/// Requests are applied by the engine at the end of the frame, in the order
+/// they were made, and only those from the active scene are honoured.
+#[derive(Default)]
+pub struct Transitions {
+    queue: Vec<Transition>,
+}
+
+impl Transitions {
+    pub fn new() -> Self {
+        Transitions {
+            queue: vec![],
+        }
+    }
+
+    pub fn push(&mut self, scene: impl Into<SceneSource>) {
+        self.queue.push(Transition::Push { scene: scene.into(), overlay: false });
+    }
+
+    /// Pushes a scene that is drawn over the paused scene below it, such as
+    /// a pause menu
+    pub fn push_overlay(&mut self, scene: impl Into<SceneSource>) {
+        self.queue.push(Transition::Push { scene: scene.into(), overlay: true });
+    }
+
+    pub fn pop(&mut self) {
+        self.queue.push(Transition::Pop);
+    }
+
+    pub fn replace(&mut self, scene: impl Into<SceneSource>) {
+        self.queue.push(Transition::Replace(scene.into()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn take(&mut self) -> Vec<Transition> {
+        std::mem::take(&mut self.queue)
+    }
+}
+
+/// Which of the scenes drawn this frame is being rendered, a resource the
+/// engine sets before running the render stage on each visible scene.
+/// Scenes are drawn bottom to top.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScenePass {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl ScenePass {
+    pub fn is_first(&self) -> bool {
+        self.index == 0
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.index + 1 == self.count
+    }
+}
+
+impl Default for ScenePass {
+    fn default() -> Self {
+        ScenePass {
+            index: 0,
+            count: 1,
+        }
+    }
+}
+
+struct Layer {
+    scene: Scene,
+    overlay: bool,
+}
+
+/// Prepares the world of a scene entering the stack, see `SceneStack::on_enter`
+pub type SceneSetup = Box<dyn Fn(&mut World)>;
+
+/// The scenes the engine is running. Only the top scene is simulated, the
+/// rest are paused until the scenes above them are popped.
+#[derive(Default)]
+pub struct SceneStack {
+    layers: Vec<Layer>,
+    /// How scenes requested by path are loaded
+    pub options: LoadOptions,
+    setup: Vec<SceneSetup>,
+}
+
+impl SceneStack {
+    pub fn new(scene: Scene) -> Self {
+        SceneStack {
+            layers: vec![Layer { scene, overlay: false }],
+            options: LoadOptions::default(),
+            setup: vec![],
+        }
+    }
+
+    /// Runs `setup` on the scenes already on the stack and on every scene
+    /// pushed or swapped in later, e.g. to give them all the same resources
+    pub fn on_enter(&mut self, setup: impl Fn(&mut World) + 'static) {
+        for layer in self.layers.iter_mut() {
+            setup(&mut layer.scene.world);
+        }
+        self.setup.push(Box::new(setup));
+    }
+
+    fn enter(&self, scene: &mut Scene) {
+        for setup in self.setup.iter() {
+            setup(&mut scene.world);
+        }
+    }
+
+    pub fn active(&self) -> Option<&Scene> {
+        self.layers.last().map(|l| &l.scene)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Scene> {
+        self.layers.last_mut().map(|l| &mut l.scene)
+    }
+
+    /// The scenes drawn this frame, from the bottom up: the active scene and
+    /// everything under a continuous run of overlays
+    pub fn visible_mut(&mut self) -> impl Iterator<Item = &mut Scene> {
+        let mut bottom = self.layers.len().saturating_sub(1);
+        while bottom > 0 && self.layers[bottom].overlay {
+            bottom -= 1;
+        }
+
+        self.layers[bottom..].iter_mut().map(|l| &mut l.scene)
+    }
+
+    pub fn push(&mut self, mut scene: Scene) {
+        self.enter(&mut scene);
+        self.layers.push(Layer { scene, overlay: false });
+    }
+
+    pub fn push_overlay(&mut self, mut scene: Scene) {
+        self.enter(&mut scene);
+        self.layers.push(Layer { scene, overlay: true });
+    }
+
+    pub fn pop(&mut self) -> Option<Scene> {
+        self.layers.pop().map(|l| l.scene)
+    }
+
+    /// Swaps the active scene, keeping whether it is an overlay
+    pub fn replace(&mut self, mut scene: Scene) -> Option<Scene> {
+        self.enter(&mut scene);
+        match self.layers.last_mut() {
+            Some(layer) => Some(std::mem::replace(&mut layer.scene, scene)),
+            None => {
+                self.layers.push(Layer { scene, overlay: false });
+                None
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Applies a transition. Newly entered scenes are sent `PlayBgm`, resumed
+    /// ones carry on as they were.
+    pub fn apply(&mut self, transition: Transition) -> Result<(), SceneError> {
+        match transition {
+            Transition::Push { scene, overlay } => {
+                let scene = scene.load(&self.options)?;
+                scene.world.send_event(PlayBgm);
+                match overlay {
+                    true => self.push_overlay(scene),
+                    false => self.push(scene),
+                }
+            },
+            Transition::Pop => { self.pop(); },
+            Transition::Replace(scene) => {
+                let scene = scene.load(&self.options)?;
+                scene.world.send_event(PlayBgm);
+                self.replace(scene);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Applies the transitions requested by the active scene. A scene that
+    /// fails to load is reported and skipped, leaving the stack as it was.
+    pub fn update(&mut self) {
+        let transitions = match self.active() {
+            Some(scene) => match scene.world.resource_mut::<Transitions>() {
+                Some(mut t) => t.take(),
+                None => return,
+            },
+            None => return,
+        };
+
+        for transition in transitions {
+            if let Err(e) = self.apply(transition) {
+                println!("Failed to load scene: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Entity;
+    use crate::events::EventReader;
+
+    fn scene(name: &str) -> Scene {
+        Scene::new(vec![Entity::named(name, vec![])])
+    }
+
+    fn active_name(stack: &SceneStack) -> Option<&'static str> {
+        let scene = stack.active()?;
+        ["title", "level", "pause", "next"].into_iter().find(|n| scene.find(n).is_some())
+    }
+
+    fn request(stack: &SceneStack, transition: impl FnOnce(&mut Transitions)) {
+        transition(&mut stack.active().unwrap().world.resource_mut::<Transitions>().unwrap());
+    }
+
+    #[test]
+    fn pushes_pops_and_replaces() {
+        let mut stack = SceneStack::new(scene("title"));
+
+        stack.apply(Transition::Push { scene: scene("level").into(), overlay: false }).unwrap();
+        assert_eq!((stack.len(), active_name(&stack)), (2, Some("level")));
+
+        stack.apply(Transition::Replace(scene("next").into())).unwrap();
+        assert_eq!((stack.len(), active_name(&stack)), (2, Some("next")));
+
+        stack.apply(Transition::Pop).unwrap();
+        assert_eq!((stack.len(), active_name(&stack)), (1, Some("title")));
+    }
+
+    #[test]
+    fn draws_scenes_under_overlays() {
+        let mut stack = SceneStack::new(scene("title"));
+        stack.push(scene("level"));
+        stack.push_overlay(scene("pause"));
+        assert_eq!(stack.visible_mut().count(), 2);
+
+        stack.replace(scene("next"));
+        assert_eq!(stack.visible_mut().count(), 2);
+
+        stack.pop();
+        assert_eq!(stack.visible_mut().count(), 1);
+    }
+
+    #[test]
+    fn applies_requests_from_the_active_scene_in_order() {
+        let mut stack = SceneStack::new(scene("title"));
+        request(&stack, |t| {
+            t.push(scene("level"));
+            t.push_overlay(scene("pause"));
+            t.pop();
+        });
+
+        stack.update();
+        assert_eq!((stack.len(), active_name(&stack)), (2, Some("level")));
+        assert!(stack.active().unwrap().world.resource::<Transitions>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn starts_music_in_entered_scenes() {
+        let mut stack = SceneStack::new(scene("title"));
+        stack.apply(Transition::Push { scene: scene("level").into(), overlay: false }).unwrap();
+
+        let mut reader = EventReader::<PlayBgm>::new();
+        assert!(reader.drain(&stack.active().unwrap().world.events::<PlayBgm>()));
+    }
+
+    #[test]
+    fn keeps_the_stack_when_a_scene_fails_to_load() {
+        let mut stack = SceneStack::new(scene("title"));
+        request(&stack, |t| t.replace("does/not/exist.xml"));
+
+        stack.update();
+        assert_eq!((stack.len(), active_name(&stack)), (1, Some("title")));
+    }
+}
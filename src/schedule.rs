@@ -0,0 +1,339 @@
+use crate::commands::Commands;
+use crate::systems::System;
+use crate::world::World;
+
+use std::any::type_name;
+use std::fmt;
+
+/// The phases of a frame, run in the order they are declared
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Input,
+    PreUpdate,
+    Update,
+    Physics,
+    PostPhysics,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Input,
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::Physics,
+        Stage::PostPhysics,
+        Stage::Render,
+    ];
+
+    /// Simulation stages run on the fixed timestep, the rest once per frame
+    pub fn is_simulation(&self) -> bool {
+        *self != Stage::Render
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Input => "input",
+            Stage::PreUpdate => "pre-update",
+            Stage::Update => "update",
+            Stage::Physics => "physics",
+            Stage::PostPhysics => "post-physics",
+            Stage::Render => "render",
+        }
+    }
+}
+
+/// A system together with where it should run in the schedule
+pub struct SystemDescriptor {
+    pub(crate) system: Box<dyn System>,
+    pub(crate) name: String,
+    pub(crate) stage: Stage,
+    pub(crate) before: Vec<String>,
+    pub(crate) after: Vec<String>,
+}
+
+impl SystemDescriptor {
+    pub fn new<S: System + 'static>(system: S) -> Self {
+        SystemDescriptor {
+            name: system.name().to_string(),
+            system: Box::new(system),
+            stage: Stage::Update,
+            before: vec![],
+            after: vec![],
+        }
+    }
+
+    pub fn in_stage(mut self, stage: Stage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn before<S: System>(self) -> Self {
+        self.before_named(type_name::<S>())
+    }
+
+    pub fn after<S: System>(self) -> Self {
+        self.after_named(type_name::<S>())
+    }
+
+    pub fn before_named(mut self, name: &str) -> Self {
+        self.before.push(name.to_string());
+        self
+    }
+
+    pub fn after_named(mut self, name: &str) -> Self {
+        self.after.push(name.to_string());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+}
+
+impl<S: System + 'static> From<S> for SystemDescriptor {
+    fn from(system: S) -> Self {
+        SystemDescriptor::new(system)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// The systems' `before`/`after` constraints contradict each other
+    Cycle(Vec<String>),
+    /// A system was asked to run before one in an earlier stage
+    StageConflict {
+        system: String,
+        other: String,
+    },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleError::Cycle(systems) => {
+                write!(f, "Systems have cyclic ordering constraints: {}", systems.join(", "))
+            },
+            ScheduleError::StageConflict { system, other } => {
+                write!(f, "{} cannot run before {}, which is in an earlier stage", system, other)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// The order systems run in, grouped by stage.
+///
+/// Queued `Commands` are applied at the end of every stage. Printing a schedule lists every stage with its systems in execution order.
+pub struct Schedule {
+    stages: Vec<(Stage, Vec<SystemDescriptor>)>,
+}
+
+impl Schedule {
+    pub fn new(systems: Vec<SystemDescriptor>) -> Result<Self, ScheduleError> {
+        check_stage_conflicts(&systems)?;
+
+        let mut systems: Vec<Option<SystemDescriptor>> = systems.into_iter().map(Some).collect();
+        let mut stages = vec![];
+
+        for stage in Stage::ALL {
+            let in_stage: Vec<SystemDescriptor> = systems.iter_mut()
+                .filter(|s| s.as_ref().is_some_and(|s| s.stage == stage))
+                .filter_map(|s| s.take())
+                .collect();
+
+            stages.push((stage, sort_stage(in_stage)?));
+        }
+
+        Ok(Schedule {
+            stages,
+        })
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        for stage in Stage::ALL {
+            self.run_stage(stage, world);
+        }
+    }
+
+    /// Runs one fixed step of every simulation stage
+    pub fn run_simulation(&mut self, world: &mut World) {
+        for stage in Stage::ALL.into_iter().filter(|s| s.is_simulation()) {
+            self.run_stage(stage, world);
+        }
+    }
+
+    pub fn run_stage(&mut self, stage: Stage, world: &mut World) {
+        for (_, systems) in self.stages.iter_mut().filter(|(s, _)| *s == stage) {
+            for descriptor in systems.iter_mut() {
+                descriptor.system.run(world);
+            }
+        }
+
+        Commands::apply(world);
+    }
+
+    /// Lists the names of a stage's systems in the order they run
+    pub fn systems(&self, stage: Stage) -> Vec<&str> {
+        self.stages.iter()
+            .filter(|(s, _)| *s == stage)
+            .flat_map(|(_, systems)| systems.iter().map(|d| d.name()))
+            .collect()
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stage, systems) in self.stages.iter() {
+            writeln!(f, "{}:", stage.name())?;
+            for descriptor in systems.iter() {
+                writeln!(f, "    {}", descriptor.name())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_stage_conflicts(systems: &[SystemDescriptor]) -> Result<(), ScheduleError> {
+    let stage_of = |name: &str| systems.iter().find(|s| s.name == name).map(|s| s.stage);
+
+    for system in systems.iter() {
+        for other in system.before.iter() {
+            if let Some(stage) = stage_of(other) {
+                if stage < system.stage {
+                    return Err(ScheduleError::StageConflict {
+                        system: system.name.clone(),
+                        other: other.clone(),
+                    });
+                }
+            }
+        }
+
+        for other in system.after.iter() {
+            if let Some(stage) = stage_of(other) {
+                if stage > system.stage {
+                    return Err(ScheduleError::StageConflict {
+                        system: other.clone(),
+                        other: system.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Orders a stage's systems by their constraints, keeping the order they were
+/// added in wherever the constraints allow. Constraints naming systems that
+/// aren't in the stage are ignored.
+fn sort_stage(systems: Vec<SystemDescriptor>) -> Result<Vec<SystemDescriptor>, ScheduleError> {
+    let count = systems.len();
+    let mut edges = vec![vec![]; count];
+    let mut incoming = vec![0; count];
+
+    let indices_of = |name: &str| -> Vec<usize> {
+        (0..count).filter(|i| systems[*i].name == name).collect()
+    };
+
+    for (i, system) in systems.iter().enumerate() {
+        for other in system.before.iter() {
+            for j in indices_of(other) {
+                edges[i].push(j);
+                incoming[j] += 1;
+            }
+        }
+
+        for other in system.after.iter() {
+            for j in indices_of(other) {
+                edges[j].push(i);
+                incoming[i] += 1;
+            }
+        }
+    }
+
+    let mut order = vec![];
+    let mut done = vec![false; count];
+    while order.len() < count {
+        let next = (0..count).find(|i| !done[*i] && incoming[*i] == 0);
+        let next = match next {
+            Some(i) => i,
+            None => {
+                let remaining = (0..count)
+                    .filter(|i| !done[*i])
+                    .map(|i| systems[i].name.clone())
+                    .collect();
+                return Err(ScheduleError::Cycle(remaining));
+            },
+        };
+
+        done[next] = true;
+        order.push(next);
+        for j in edges[next].iter() {
+            incoming[*j] -= 1;
+        }
+    }
+
+    let mut systems: Vec<Option<SystemDescriptor>> = systems.into_iter().map(Some).collect();
+    Ok(order.into_iter()
+        .filter_map(|i| systems[i].take())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct A;
+    struct B;
+    struct C;
+
+    impl System for A { fn run(&mut self, _: &mut World) {} }
+    impl System for B { fn run(&mut self, _: &mut World) {} }
+    impl System for C { fn run(&mut self, _: &mut World) {} }
+
+    #[test]
+    fn orders_systems_by_their_constraints() {
+        let schedule = Schedule::new(vec![
+            SystemDescriptor::new(A).after::<C>(),
+            SystemDescriptor::new(B),
+            SystemDescriptor::new(C).after::<B>(),
+        ]).unwrap();
+
+        assert_eq!(schedule.systems(Stage::Update), [type_name::<B>(), type_name::<C>(), type_name::<A>()]);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let result = Schedule::new(vec![
+            SystemDescriptor::new(A).before::<B>(),
+            SystemDescriptor::new(B).before::<C>(),
+            SystemDescriptor::new(C).before::<A>(),
+        ]);
+
+        match result {
+            Err(ScheduleError::Cycle(systems)) => assert_eq!(systems.len(), 3),
+            _ => panic!("expected a cycle"),
+        }
+    }
+
+    #[test]
+    fn reports_stage_conflicts() {
+        let result = Schedule::new(vec![
+            SystemDescriptor::new(A).in_stage(Stage::Physics).before::<B>(),
+            SystemDescriptor::new(B).in_stage(Stage::Input),
+        ]);
+
+        let expected = ScheduleError::StageConflict {
+            system: type_name::<A>().to_string(),
+            other: type_name::<B>().to_string(),
+        };
+        assert_eq!(result.err(), Some(expected));
+    }
+}
@@ -1,14 +1,20 @@
+pub mod animation;
 pub mod audio;
+pub mod camera;
 pub mod collision;
 pub mod input;
 pub mod physics;
 pub mod render;
 pub mod movement;
+pub mod transform;
 
-use crate::entity::Entity;
-use std::sync::Arc;
-use std::sync::Mutex;
+use crate::world::World;
 
 pub trait System {
-    fn run(&mut self, entities: &mut [Arc<Mutex<Entity>>]);
-}
\ No newline at end of file
+    fn run(&mut self, world: &mut World);
+
+    /// Identifies the system when editing an `EngineBuilder`
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
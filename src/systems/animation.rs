@@ -0,0 +1,60 @@
+use crate::components::animation::{AnimationComponent, FrameSource};
+use crate::components::sprite::SpriteComponent;
+use crate::events::AnimationFinished;
+use crate::systems::System;
+use crate::time::Time;
+use crate::world::World;
+
+/// Plays entities' animations, showing each frame on their sprite
+pub struct AnimationSystem { }
+
+impl AnimationSystem {
+    pub fn new() -> Self {
+        AnimationSystem {}
+    }
+
+    fn show_frame(&self, animation: &AnimationComponent, sprite: &mut SpriteComponent) {
+        let source = match animation.frame() {
+            Some(f) => &f.source,
+            None => return,
+        };
+
+        match source {
+            // Strict scenes are checked for missing frames when loaded, others
+            // keep showing the last frame that could be found
+            FrameSource::Named(name) => { sprite.set_frame(name); },
+            FrameSource::Cell(_) => {
+                let size = sprite.texture.as_ref().map(|t| (t.image().width, t.image().height));
+                if let Some(region) = size.and_then(|s| animation.cell_region(s)) {
+                    sprite.source = Some(region);
+                }
+            },
+        }
+    }
+}
+
+impl Default for AnimationSystem {
+    fn default() -> Self {
+        AnimationSystem::new()
+    }
+}
+
+impl System for AnimationSystem {
+    fn run(&mut self, world: &mut World) {
+        let delta = world.resource::<Time>()
+            .map_or(0.0, |t| t.delta_seconds());
+
+        world.query::<(&mut AnimationComponent, &mut SpriteComponent)>().for_each(|entity, (animation, sprite)| {
+            if animation.advance(delta) {
+                world.send_event(AnimationFinished {
+                    entity,
+                    clip: animation.clip().map(|c| c.name.clone()).unwrap_or_default(),
+                });
+            }
+
+            if animation.take_changed() {
+                self.show_frame(animation, sprite);
+            }
+        });
+    }
+}
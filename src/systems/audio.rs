@@ -1,41 +1,45 @@
-use crate::global::Global;
-use crate::entity::Entity;
-use crate::components::Component;
+use crate::components::audio::AudioComponent;
+use crate::events::{EventReader, PlayBgm, PlaySfx};
 use crate::systems::System;
+use crate::world::World;
 
-use std::sync::{Arc, Mutex};
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
-use rodio::{Decoder, OutputStream, Sink};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
 use rodio::source::Source;
 
 pub struct AudioSystem {
     bgm: Sink,
     bgm_stream: OutputStream,
-    global: Arc<Mutex<Global>>,
+    bgm_handle: OutputStreamHandle,
+    play_bgm: EventReader<PlayBgm>,
+    play_sfx: EventReader<PlaySfx>,
 }
 
 impl AudioSystem {
-    pub fn new(global: Arc<Mutex<Global>>) -> Self {
-        let (bgm_stream, bgm_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&bgm_handle).expect("Could not create audio sink"); 
-
-        {
-            let global = global.clone();
-            let mut global = global.lock().expect("Could not unlock global object");
-            global.signals.insert("play_bgm".to_string(), true);
-        }
+    pub fn new() -> Self {
+        AudioSystem::try_new().expect("Could not open audio device")
+    }
+
+    /// Opens the default audio device, failing if there isn't one
+    pub fn try_new() -> Result<Self, Box<dyn Error>> {
+        let (bgm_stream, bgm_handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&bgm_handle)?;
 
-        AudioSystem {
+        Ok(AudioSystem {
             bgm: sink,
             bgm_stream: bgm_stream,
-            global,
-        }
+            bgm_handle,
+            play_bgm: EventReader::new(),
+            play_sfx: EventReader::new(),
+        })
     }
 
-    pub fn play_bgm(&self, path: &Path) {
+    /// Replaces whatever background music is playing, e.g. on entering a new
+    /// scene
+    pub fn play_bgm(&mut self, path: &Path) {
         let source = match self.get_source(path) {
             Ok(s) => s.repeat_infinite(),
             Err(e) => {
@@ -44,13 +48,12 @@ impl AudioSystem {
             }
         };
 
+        self.bgm = Sink::try_new(&self.bgm_handle).expect("Could not create audio sink");
         self.bgm.append(source);
         println!("Playing {}", path.display());
     }
 
     pub fn play_sfx(&self, path: &Path) {
-        let (_stream, handle) = OutputStream::try_default().unwrap();
-
         let source = match self.get_source(path) {
             Ok(s) => s,
             Err(e) => {
@@ -59,7 +62,7 @@ impl AudioSystem {
             }
         };
 
-        let play_result = handle.play_raw(source.convert_samples());
+        let play_result = self.bgm_handle.play_raw(source.convert_samples());
         if let Err(e) = play_result {
             println!("{}", e);
         }
@@ -74,46 +77,29 @@ impl AudioSystem {
     }
 }
 
-impl System for AudioSystem {
-    fn run(&mut self, entities: &mut [Arc<Mutex<Entity>>]) {
-        let mut play_bgm = false;
-        let mut play_sfx = false;
-
-        {
-            let global = self.global.clone();
-            let mut global = global.lock().expect("Could not unlock global object");
-
-            if let Some(true) = global.signals.get("play_bgm") {
-                play_bgm = true;
-                global.signals.insert("play_bgm".to_string(), false);
-            }
+impl Default for AudioSystem {
+    fn default() -> Self {
+        AudioSystem::new()
+    }
+}
 
-            if let Some(true) = global.signals.get("play_sfx") {
-                play_sfx = true;
-                global.signals.insert("play_sfx".to_string(), false);
-            }
-        }
+impl System for AudioSystem {
+    fn run(&mut self, world: &mut World) {
+        let play_bgm = self.play_bgm.drain(&world.events::<PlayBgm>());
+        let play_sfx = self.play_sfx.drain(&world.events::<PlaySfx>());
 
         if !(play_bgm || play_sfx) {
             return;
         }
 
-        for entity in entities {
-            let unlocked_entity = entity.clone();
-            let unlocked_entity = unlocked_entity.lock().expect("Could not acquire entity");
-            for component in unlocked_entity.components.iter() {
-                if let Component::Audio(audio) = component {
-                    if let (true, Some(sfx)) = (play_sfx, audio.sfx.as_ref()) {
-                        self.play_sfx(Path::new(sfx));
-                    }
-
-                    if let (true, Some(bgm)) = (play_bgm, audio.bgm.as_ref()) {
-                        self.play_bgm(Path::new(bgm));
-                    }
-
-                    break;
-                }
+        world.query::<&AudioComponent>().for_each(|_, audio| {
+            if let (true, Some(sfx)) = (play_sfx, audio.sfx.as_ref()) {
+                self.play_sfx(Path::new(sfx));
             }
-        }
+
+            if let (true, Some(bgm)) = (play_bgm, audio.bgm.as_ref()) {
+                self.play_bgm(Path::new(bgm));
+            }
+        });
     }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use crate::components::camera::CameraComponent;
+use crate::components::position::PositionComponent;
+use crate::global::Global;
+use crate::systems::System;
+use crate::time::Time;
+use crate::world::World;
+
+use std::sync::{Arc, Mutex};
+
+/// The camera a world is seen through: the earliest spawned entity's camera,
+/// or one showing the view from the origin at the top left if it has none
+pub fn active_camera(world: &World, view_size: (f32, f32)) -> CameraComponent {
+    world.query::<&CameraComponent>()
+        .entities()
+        .into_iter()
+        .min_by_key(|e| world.spawn_order(*e))
+        .and_then(|e| world.get::<CameraComponent>(e).map(|c| c.clone()))
+        .unwrap_or_else(|| {
+            let mut camera = CameraComponent::default();
+            camera.position = (view_size.0 / 2.0, view_size.1 / 2.0);
+            camera
+        })
+}
+
+/// Moves cameras after their followed entities, keeps them within their
+/// bounds and plays out their shakes
+pub struct CameraSystem {
+    global: Arc<Mutex<Global>>,
+}
+
+impl CameraSystem {
+    pub fn new(global: Arc<Mutex<Global>>) -> Self {
+        CameraSystem {
+            global,
+        }
+    }
+}
+
+impl System for CameraSystem {
+    fn run(&mut self, world: &mut World) {
+        let view_size = self.global
+            .lock()
+            .expect("Could not unlock global object")
+            .view_size;
+        let delta = world.resource::<Time>()
+            .map_or(0.0, |t| t.delta_seconds());
+
+        let cameras = world.query::<&CameraComponent>().entities();
+        for entity in cameras {
+            let mut camera = match world.get_mut::<CameraComponent>(entity) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let target = camera.follow.as_deref()
+                .and_then(|name| world.find(name))
+                .and_then(|e| world.get::<PositionComponent>(e).map(|p| p.get_centre()));
+
+            camera.update(target, view_size, delta);
+        }
+    }
+}
@@ -1,11 +1,9 @@
+use crate::components::collision::CollisionComponent;
 use crate::components::physics::PhysicsComponent;
 use crate::components::position::PositionComponent;
-use crate::entity::Entity;
-use crate::components::Component;
+use crate::events::Collision;
 use crate::systems::System;
-
-use std::sync::Arc;
-use std::sync::Mutex;
+use crate::world::World;
 
 pub struct CollisionSystem { }
 
@@ -14,9 +12,10 @@ impl CollisionSystem {
         CollisionSystem {}
     }
 
-    pub fn handle_collision(&self, intersection: &[f32; 4], a: (&mut PositionComponent, &mut PhysicsComponent), b: &mut PositionComponent) {
+    /// Pushes entity A out of B, whose box has the edges `b`, and bounces it
+    /// off. Returns how far A was moved.
+    pub fn handle_collision(&self, intersection: &[f32; 4], a: (&mut PositionComponent, &mut PhysicsComponent), b: &[f32; 4]) -> (f32, f32) {
         let pos_a = a.0;
-        let pos_b = b;
         let phys_a = a.1;
         
         let x_dist = intersection[1] - intersection[0];
@@ -27,120 +26,99 @@ impl CollisionSystem {
             Axis::X => {
                 phys_a.bounce_x();
                 phys_a.friction_y();
-                if pos_b.position.0 == intersection[0] { Edge::Left } else { Edge::Right }
+                if b[0] == intersection[0] { Edge::Left } else { Edge::Right }
             },
             Axis::Y => {
                 phys_a.bounce_y();
                 phys_a.friction_x();
-                if pos_b.position.1 == intersection[2] { Edge::Top } else { Edge::Bottom }
+                if b[2] == intersection[2] { Edge::Top } else { Edge::Bottom }
             },
         };
 
-        match edge {
-            Edge::Left => {
-                pos_a.position.0 -= x_dist;
-            },
-            Edge::Right => {
-                pos_a.position.0 += x_dist;
-            },
+        let shift = match edge {
+            Edge::Left => (-x_dist, 0.0),
+            Edge::Right => (x_dist, 0.0),
             Edge::Top => {
-                pos_a.position.1 -= y_dist;
                 /*
                 if phys_a.velocity.1.abs() < global.1.abs() {
                     phys_a.airtime = 0;
                 }
                 */
+                (0.0, -y_dist)
             },
-            Edge::Bottom => {
-                pos_a.position.1 += y_dist;
-            },
-        }
+            Edge::Bottom => (0.0, y_dist),
+        };
+
+        pos_a.shift(shift.0, shift.1);
+        shift
     }
 }
 
 impl System for CollisionSystem {
-    fn run(&mut self, entities: &mut [Arc<Mutex<Entity>>]) {
-        let entities = entities.to_vec();
+    fn run(&mut self, world: &mut World) {
+        // Both entities must be collideable and have a position
+        let entities = world.query::<(&CollisionComponent, &PositionComponent)>().entities();
+
+        // Work out every box once, moving it along whenever its entity is
+        // pushed out of another
+        let mut bounds: Vec<[f32; 4]> = entities.iter()
+            .map(|e| world.get::<PositionComponent>(*e).map_or([0.0; 4], |p| p.bounds()))
+            .collect();
 
         // Check for collisions between all eligible entities
-        for a in entities.iter() {
-            for b in entities.iter() {
+        for (i, a) in entities.iter().enumerate() {
+            // Only entity A needs a PhysicsComponent, as it is the collider
+            let mut phys_a = match world.get_mut::<PhysicsComponent>(*a) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            for (j, b) in entities.iter().enumerate() {
                 // Don't check an entity against itself
-                if Arc::ptr_eq(a, b) { continue }
-
-                // Only entity A needs a PhysicsComponent, as it is the collider
-                let mut pos_a = None;
-                let mut pos_b = None;
-                let mut phys_a = None;
-                let mut coll_a = false;
-                let mut coll_b = false;
-
-                let unlocked_a = a.clone();
-                let unlocked_b = b.clone();
-
-                let mut unlocked_a = unlocked_a.lock().expect("Could not acquire entity");
-                let mut unlocked_b = unlocked_b.lock().expect("Could not acquire entity");
-
-                let components_a = &mut unlocked_a.components;
-                let components_b = &mut unlocked_b.components;
-
-                // Extract relevant components
-                for component in components_a.iter_mut() {
-                    match component {
-                        Component::Collision(_) => coll_a = true,
-                        Component::Position(c) => pos_a = Some(c),
-                        Component::Physics(c) => phys_a = Some(c),
-                        _ => {},
-                    }
-                }
-
-                for component in components_b.iter_mut() {
-                    match component {
-                        Component::Collision(_) => coll_b = true,
-                        Component::Position(c) => pos_b = Some(c),
-                        _ => {},
-                    }
-                }
-
-                // Both entities must be collideable
-                if !(coll_a && coll_b) { continue }
-
-                if let (Some(pos_a), Some(pos_b), Some(phys_a)) = (pos_a, pos_b, phys_a) {
-                    if !are_colliding(pos_a, pos_b) { continue }
-
-                    let intersection = get_collision_intersection(pos_a, pos_b);
-                    self.handle_collision(&intersection, (pos_a, phys_a), pos_b);
-                }
+                if i == j { continue }
+
+                if !are_colliding(&bounds[i], &bounds[j]) { continue }
+
+                let mut pos_a = match world.get_mut::<PositionComponent>(*a) {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                let intersection = get_collision_intersection(&bounds[i], &bounds[j]);
+                let (dx, dy) = self.handle_collision(&intersection, (&mut pos_a, &mut phys_a), &bounds[j]);
+                let moved = &mut bounds[i];
+                moved[0] += dx;
+                moved[1] += dx;
+                moved[2] += dy;
+                moved[3] += dy;
+
+                world.send_event(Collision {
+                    a: *a,
+                    b: *b,
+                });
             }
         }
     }
 }
 
-pub fn are_colliding(a: &PositionComponent, b: &PositionComponent) -> bool {
-    let a_right_b = a.get_left_pos() > b.get_right_pos();
-    let a_left_b = a.get_right_pos() < b.get_left_pos();
-    let a_above_b = a.get_bottom_pos() < b.get_top_pos();
-    let a_below_b = a.get_top_pos() > b.get_bottom_pos();
+/// Whether two boxes, given by their left, right, top and bottom edges,
+/// overlap
+pub fn are_colliding(a: &[f32; 4], b: &[f32; 4]) -> bool {
+    let (left, right, top, bottom) = (0, 1, 2, 3);
+
+    let a_right_b = a[left] > b[right];
+    let a_left_b = a[right] < b[left];
+    let a_above_b = a[bottom] < b[top];
+    let a_below_b = a[top] > b[bottom];
 
     !(a_right_b || a_left_b || a_above_b || a_below_b)
 }
 
-pub fn get_collision_intersection(a: &PositionComponent, b: &PositionComponent) -> [f32; 4] {
+/// The overlap of two boxes, given and returned as their left, right, top
+/// and bottom edges
+pub fn get_collision_intersection(a_edges: &[f32; 4], b_edges: &[f32; 4]) -> [f32; 4] {
     let (left, right, top, bottom) = (0, 1, 2, 3);
 
-    let a_edges = [
-        a.position.0,
-        a.position.0 + a.size.0,
-        a.position.1,
-        a.position.1 + a.size.1,
-    ];
-    let b_edges = [
-        b.position.0,
-        b.position.0 + b.size.0,
-        b.position.1,
-        b.position.1 + b.size.1,
-    ];
-
     let intersection_edges = [
         max_f32(a_edges[left], b_edges[left]),
         min_f32(a_edges[right], b_edges[right]),
@@ -175,4 +153,29 @@ pub enum Edge {
     Right,
     Top,
     Bottom,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Component;
+    use crate::events::EventReader;
+
+    #[test]
+    fn pushes_colliders_out_of_everything_they_overlap() {
+        let mut world = World::new();
+        world.add_event::<Collision>();
+        let box_at = |x: f32, y: f32| PositionComponent::new((x, y), (10.0, 10.0));
+        let ball = world.spawn(vec![box_at(0.0, 5.0), CollisionComponent::new(), Component::Physics(PhysicsComponent::default())]);
+        let floor = world.spawn(vec![box_at(-5.0, 12.0), CollisionComponent::new()]);
+        world.spawn(vec![box_at(5.0, 13.0), CollisionComponent::new()]);
+
+        CollisionSystem::new().run(&mut world);
+
+        // Once out of the floor, the ball no longer reaches down to the ledge
+        assert_eq!(world.get::<PositionComponent>(ball).unwrap().position, (0.0, 2.0));
+        let mut reader = EventReader::<Collision>::new();
+        let collisions: Vec<_> = reader.read(&world.events::<Collision>()).map(|c| (c.a, c.b)).collect();
+        assert_eq!(collisions, [(ball, floor)]);
+    }
+}
@@ -1,6 +1,8 @@
+use crate::events::{Clicked, Key, KeyEvent};
 use crate::global::Global;
-use crate::entity::Entity;
 use crate::systems::System;
+use crate::systems::camera::active_camera;
+use crate::world::World;
 
 use std::sync::{Arc, Mutex};
 use winit::dpi::PhysicalPosition;
@@ -27,81 +29,65 @@ impl InputSystem {
         }
     }
 
-    pub fn handle_input(&mut self, input: KeyboardInput) {
-        match input.scancode {
+    pub fn handle_input(&mut self, input: KeyboardInput, world: &World) {
+        let pressed = match input.state {
+            ElementState::Pressed => true,
+            ElementState::Released => false,
+        };
+
+        let key = match input.scancode {
             // Clockwise arrow keys
             103 | 17 => {
-                self.up = match input.state {
-                    ElementState::Pressed => true,
-                    ElementState::Released => false,
-                };
-            }
+                self.up = pressed;
+                Key::Up
+            },
             106 | 32 => {
-                self.right = match input.state {
-                    ElementState::Pressed => true,
-                    ElementState::Released => false,
-                };
+                self.right = pressed;
+                Key::Right
             },
             108 | 31 => {
-                self.down = match input.state {
-                    ElementState::Pressed => true,
-                    ElementState::Released => false,
-                };
+                self.down = pressed;
+                Key::Down
             },
             105 | 30 => {
-                self.left = match input.state {
-                    ElementState::Pressed => true,
-                    ElementState::Released => false,
-                };
+                self.left = pressed;
+                Key::Left
             },
-            _ => {},
-        }
-
-        let global = self.global.clone();
-        let mut global = global.lock().expect("Could not unlock global object");
-
-        global.signals.insert("up_pressed".to_string(), self.up);
-        global.signals.insert("down_pressed".to_string(), self.down);
-        global.signals.insert("left_pressed".to_string(), self.left);
-        global.signals.insert("right_pressed".to_string(), self.right);
+            _ => return,
+        };
 
+        world.send_event(KeyEvent {
+            key,
+            pressed,
+        });
     }
 
+    /// Tracks the cursor from -1 to 1 across and down the view, which may
+    /// only cover part of the window
     pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
-        let dims: (f32, f32);
-        let origin: (f32, f32);
-
-        {
-            let global = self.global.clone();
-            let global = global.lock().expect("Could not unlock global object");
-            dims = global.view_size;
-            origin = global.view_origin;
-        }
-
-        let real_dims = [dims.0, dims.1];
-        let view_dims = [
-            real_dims[0] - 2.0 * origin.0,
-            real_dims[1] - 2.0 * origin.1,
-        ];
-
-        let mut pos: [f32; 2] = position.into();
-        pos = [
-            (2.0 * (pos[0] - real_dims[0] / 2.0) / real_dims[0]),
-            (2.0 * (pos[1] - real_dims[1] / 2.0) / real_dims[1]),
+        let (origin, size) = self.global
+            .lock()
+            .expect("Could not unlock global object")
+            .viewport();
+
+        let pos: [f32; 2] = position.into();
+        self.cursor = [
+            2.0 * (pos[0] - origin.0) / size.0 - 1.0,
+            2.0 * (pos[1] - origin.1) / size.1 - 1.0,
         ];
-        pos[0] *= real_dims[0] / view_dims[0];
-        pos[1] *= real_dims[1] / view_dims[1];
-
-        self.cursor = pos;
     }
 
-    pub fn click(&self) {
+    pub fn click(&self, world: &World) {
         if !self.is_valid_cursor_position() { return; }
 
         let global = self.global.clone();
         let mut global = global.lock().expect("Could not unlock global object");
-        global.click = (self.cursor[0], self.cursor[1]);
-        global.signals.insert("resize".to_string(), true);
+        let camera = active_camera(world, global.view_size);
+        global.click = camera.to_world((self.cursor[0], self.cursor[1]), global.view_size);
+
+        world.send_event(Clicked {
+            position: global.click,
+        });
     }
 
     fn is_valid_cursor_position(&self) -> bool {
@@ -113,5 +99,25 @@ impl InputSystem {
 }
 
 impl System for InputSystem {
-    fn run(&mut self, _entities: &mut [Arc<Mutex<Entity>>]) { }
-}
\ No newline at end of file
+    fn run(&mut self, _world: &mut World) { }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventReader, Resized};
+    use crate::scene::Scene;
+
+    #[test]
+    fn clicks_only_send_clicked() {
+        let scene = Scene::new(vec![]);
+        let mut input = InputSystem::new(Global::new("test".to_string(), (320, 240), (320.0, 240.0)));
+
+        input.cursor_moved(PhysicalPosition::new(80.0, 60.0));
+        input.click(&scene.world);
+
+        let clicks: Vec<Clicked> = EventReader::new().read(&scene.world.events::<Clicked>()).cloned().collect();
+        assert_eq!(clicks.len(), 1);
+        assert_eq!(clicks[0].position, (80.0, 60.0));
+        assert!(!EventReader::<Resized>::new().drain(&scene.world.events::<Resized>()));
+    }
+}
@@ -1,9 +1,8 @@
 use crate::global::Global;
 use crate::components::physics::PhysicsComponent;
 use crate::components::position::PositionComponent;
-use crate::entity::Entity;
-use crate::components::Component;
 use crate::systems::System;
+use crate::world::World;
 use std::sync::{Arc, Mutex};
 
 pub struct PhysicsSystem {
@@ -59,26 +58,8 @@ impl PhysicsSystem {
 }
 
 impl System for PhysicsSystem {
-    fn run(&mut self, entities: &mut [Arc<Mutex<Entity>>]) {
-        for entity in entities {
-            let mut position = None;
-            let mut physics = None;
-
-            let unlocked_entity = entity.clone();
-            let mut unlocked_entity = unlocked_entity.lock().expect("Could not acquire entity");
-            let components = &mut unlocked_entity.components;
-
-            for component in components.iter_mut() {
-                match component {
-                    Component::Position(c) => position = Some(c),
-                    Component::Physics(c) => physics = Some(c),
-                    _ => {},
-                }
-            }
-
-            if let (Some(physics), Some(position)) = (physics, position) {
-                self.update_position(physics, position)
-            }
-        }
+    fn run(&mut self, world: &mut World) {
+        world.query::<(&mut PhysicsComponent, &mut PositionComponent)>()
+            .for_each(|_, (physics, position)| self.update_position(physics, position));
     }
 }
\ No newline at end of file
@@ -1,23 +1,100 @@
-use crate::components::Component;
+use crate::capture::Image;
+use crate::components::camera::Matrix;
+use crate::components::position::PositionComponent;
+use crate::components::sprite::SpriteComponent;
+use crate::events::{CaptureFrame, EventReader, FrameCaptured, Resized};
 use crate::global::Global;
-use crate::entity::Entity;
-use crate::renderer::Renderer;
+use crate::layers::Layers;
+use crate::renderer::{Renderer, Target};
+use crate::scene::stack::ScenePass;
 use crate::shaders::Shader;
 use crate::systems::System;
-
-use std::sync::{Arc, Mutex};
-use vulkano::buffer::{TypedBufferAccess, CpuAccessibleBuffer, BufferUsage};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents};
+use crate::systems::camera::active_camera;
+use crate::texture::{Texture, TextureKey};
+use crate::vertex::Vertex;
+use crate::world::World;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use vulkano::buffer::{BufferUsage, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer, SubpassContents};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
-use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::image::ImmutableImage;
+use vulkano::image::view::ImageView;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::swapchain::SwapchainAcquireFuture;
 use vulkano::sync;
 use vulkano::sync::{GpuFuture, FlushError};
+use winit::window::Window;
+
+/// What the renderer drew in the last frame, across every visible scene.
+/// A resource in the active scene's world, updated once per frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub sprites: usize,
+    /// Runs of sprites sharing a pipeline and texture
+    pub batches: usize,
+    /// One per batch, each drawing its sprites from a shared buffer
+    pub draw_calls: usize,
+    pub pipeline_binds: usize,
+    pub descriptor_set_binds: usize,
+}
+
+/// The push constants every vertex shader declares as `View`
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ViewUniform {
+    view_projection: Matrix,
+}
+
+/// A sprite's vertices, already in the pass's vertex buffer, and its indices
+/// relative to them
+struct Quad {
+    layer: usize,
+    z: f32,
+    /// When the sprite's entity was spawned, see `World::spawn_order`
+    order: u64,
+    shader: Shader,
+    texture: Option<Texture>,
+    base_vertex: u32,
+    indices: Vec<u16>,
+}
+
+/// Sprites drawn with one call, a range of the pass's index buffer
+struct Batch {
+    shader: Shader,
+    texture: Option<Texture>,
+    first_index: u32,
+    index_count: u32,
+}
+
+/// A texture on the GPU, kept until no sprite holds the texture any more
+struct UploadedTexture {
+    image: Weak<Image>,
+    view: Arc<ImageView<ImmutableImage>>,
+}
+
+/// A frame being recorded, which may take several scene passes to draw
+struct Frame {
+    builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    image_num: usize,
+    acquire_future: Option<SwapchainAcquireFuture<Window>>,
+}
 
 pub struct RenderSystem {
     renderer: Renderer,
     global: Arc<Mutex<Global>>,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
     recreate_swapchain: bool,
+    resized: EventReader<Resized>,
+    capture_frame: EventReader<CaptureFrame>,
+    captures: Vec<CaptureFrame>,
+    frame: Option<Frame>,
+    vertex_pool: CpuBufferPool<Vertex>,
+    index_pool: CpuBufferPool<u32>,
+    textures: HashMap<TextureKey, UploadedTexture>,
+    descriptor_sets: HashMap<TextureKey, Arc<PersistentDescriptorSet>>,
+    stats: RenderStats,
 }
 
 impl RenderSystem {
@@ -25,67 +102,39 @@ impl RenderSystem {
         RenderSystem {
             previous_frame_end: Some(sync::now(renderer.device.clone()).boxed()),
             recreate_swapchain: false,
+            resized: EventReader::new(),
+            capture_frame: EventReader::new(),
+            captures: vec![],
+            frame: None,
+            vertex_pool: CpuBufferPool::vertex_buffer(renderer.device.clone()),
+            index_pool: CpuBufferPool::new(renderer.device.clone(), BufferUsage::index_buffer()),
+            textures: HashMap::new(),
+            descriptor_sets: HashMap::new(),
+            stats: RenderStats::default(),
             renderer,
             global,
         }
     }
 
     fn resize(&mut self) {
-        let global = self.global.clone();
-        let global = global.lock().expect("Could not unlock global object");
-        let window_size = global.window_size;
-        let view_size = global.view_size;
-
-        let x = window_size.0 as f32;
-        let y = window_size.1 as f32;
-
-        let res_ratio = view_size.0 as f32 / view_size.1 as f32;
-        let win_ratio = window_size.0 as f32 / window_size.1 as f32;
-
-        if win_ratio > res_ratio {
-            let vx = y * res_ratio;
-            let vy = y;
-
-            self.renderer.viewport.dimensions = [vx, vy];
-            self.renderer.viewport.origin = [
-                (x / 2.0) - (vx / 2.0),
-                0.0,
-            ];
-        } else {
-            let vx = x;
-            let vy = x / res_ratio;
-
-            self.renderer.viewport.dimensions = [vx, vy];
-            self.renderer.viewport.origin = [
-                0.0,
-                (y / 2.0) - (vy / 2.0),
-            ];
-        }
+        let (origin, dimensions) = self.global
+            .lock()
+            .expect("Could not unlock global object")
+            .viewport();
+
+        self.renderer.viewport.origin = [origin.0, origin.1];
+        self.renderer.viewport.dimensions = [dimensions.0, dimensions.1];
 
         self.renderer.recreate_pipelines().unwrap();
+        self.descriptor_sets.clear();
         self.recreate_swapchain = true
     }
-}
 
-impl System for RenderSystem {
-    fn run(&mut self, entities: &mut [Arc<Mutex<Entity>>]) {
-        self.recreate_swapchain = false;
+    /// Acquires the next swapchain image and starts recording into it
+    fn begin_frame(&mut self) {
         self.previous_frame_end = Some(sync::now(self.renderer.device.clone()).boxed());
-
-        let mut resize = false;
-        {
-            let global = self.global.clone();
-            let mut global = global.lock().expect("Could not unlock global object");
-            if let Some(true) = global.signals.get("resize") {
-                resize = true;
-                global.signals.insert("resize".to_string(), false);
-            }
-        }
-
-        if resize { self.resize() }
-        //self.update_position(&input_handler, entity_index);
-
         self.previous_frame_end.as_mut().unwrap().cleanup_finished();
+        self.evict_textures();
 
         if self.recreate_swapchain {
             if let Err(_) = self.renderer.recreate_swapchain() {
@@ -124,80 +173,193 @@ impl System for RenderSystem {
             .unwrap()
             .set_viewport(0, [self.renderer.viewport.clone()]);
 
+        self.frame = Some(Frame {
+            builder,
+            image_num,
+            acquire_future,
+        });
+    }
 
-        for entity in entities {
-            let unlocked_entity = entity.clone();
-            let unlocked_entity = unlocked_entity.lock().expect("Could not acquire entity");
-            let components = &unlocked_entity.components;
-
-            // Entities need a SpriteComponent and a PositionComponent in order to be drawn
-            let mut position = None;
-            let mut sprite = None;
+    /// Records draws for every sprite in the world into the current frame,
+    /// as seen through its `active_camera`.
+    ///
+    /// Sprites are drawn in the order `collect_quads` puts them in. Each run
+    /// of consecutive sprites sharing a pipeline and texture is drawn with a
+    /// single call. The geometry of the whole pass shares one vertex and one
+    /// index buffer, taken from pools that reuse their memory across frames.
+    fn draw(&mut self, world: &World) {
+        let mut frame = match self.frame.take() {
+            Some(f) => f,
+            None => return,
+        };
+
+        let mut vertices = vec![];
+        let quads = collect_quads(world, &mut vertices);
+        self.stats.sprites += quads.len();
+
+        let mut indices: Vec<u32> = vec![];
+        let mut batches: Vec<Batch> = vec![];
+        for quad in quads {
+            let first_index = indices.len() as u32;
+            indices.extend(quad.indices.iter().map(|i| quad.base_vertex + *i as u32));
+            let index_count = quad.indices.len() as u32;
+
+            match batches.last_mut() {
+                Some(batch) if batch.shader == quad.shader && batch.texture == quad.texture => {
+                    batch.index_count += index_count;
+                },
+                _ => batches.push(Batch {
+                    shader: quad.shader,
+                    texture: quad.texture,
+                    first_index,
+                    index_count,
+                }),
+            }
+        }
 
-            for component in components.iter() {
-                match component {
-                    Component::Position(c) => position = Some(c),
-                    Component::Sprite(c) => sprite = Some(c),
-                    _ => {},
+        self.stats.batches += batches.len();
+
+        let view_size = self.global
+            .lock()
+            .expect("Could not unlock global object")
+            .view_size;
+        let view = active_camera(world, view_size).view_projection(view_size);
+
+        if !batches.is_empty() {
+            let vertices = self.vertex_pool.chunk(vertices)
+                .expect("Failed to create buffer");
+            let indices = self.index_pool.chunk(indices)
+                .expect("Failed to create buffer");
+
+            frame.builder
+                .bind_vertex_buffers(0, vertices)
+                .bind_index_buffer(indices);
+
+            let mut bound = None;
+            for batch in batches {
+                let pipeline = self.renderer.pipelines[&batch.shader].clone();
+                if bound != Some(batch.shader) {
+                    frame.builder
+                        .bind_pipeline_graphics(pipeline.clone())
+                        .push_constants(pipeline.layout().clone(), 0, ViewUniform { view_projection: view });
+                    bound = Some(batch.shader);
+                    self.stats.pipeline_binds += 1;
                 }
-            }
 
-            if let (Some(position), Some(sprite)) = (position, sprite) {
-                let vertices = Renderer::create_vertex_buffer(position.vertices.clone(), &self.renderer.device);
-                let indices = CpuAccessibleBuffer::from_iter(self.renderer.device.clone(), BufferUsage::all(), false, position.indices.clone())
-                    .expect("Failed to create buffer");
-                let pipeline = self.renderer.pipelines[&sprite.shader].clone();
-
-                builder
-                    .bind_pipeline_graphics(pipeline.clone());
-
-                if let (Shader::Texture, Some(s)) = (&sprite.shader, &sprite.texture) {
-                    let (texture, texture_future) = self.renderer.create_texture(s);
-                    let layout = pipeline.layout().descriptor_set_layouts().get(0).unwrap();
-                    let set = PersistentDescriptorSet::new(
-                        layout.clone(),
-                        [WriteDescriptorSet::image_view_sampler(
-                            0,
-                            texture,
-                            self.renderer.sampler.clone(),
-                        )],
-                    ).unwrap();
-
-                    self.previous_frame_end = Some(texture_future.boxed());
-
-                    builder.bind_descriptor_sets(
+                if let Some(texture) = &batch.texture {
+                    let set = self.texture_set(texture, &pipeline);
+                    frame.builder.bind_descriptor_sets(
                         PipelineBindPoint::Graphics,
                         pipeline.layout().clone(),
                         0,
-                        set.clone(),
+                        set,
                     );
+                    self.stats.descriptor_set_binds += 1;
                 }
 
-                builder
-                    .bind_vertex_buffers(0, vertices.clone())
-                    .bind_index_buffer(indices.clone())
-                    .draw_indexed(indices.len() as u32, vertices.len() as u32, 0, 0, 0)
+                frame.builder
+                    .draw_indexed(batch.index_count, 1, batch.first_index, 0, 0)
                     .unwrap();
+                self.stats.draw_calls += 1;
             }
         }
 
+        self.frame = Some(frame);
+    }
+
+    /// Drops the uploads of textures that every sprite has let go of, such
+    /// as those of popped scenes. Frames still in flight keep their own
+    /// references, so the memory is only freed once they are done.
+    fn evict_textures(&mut self) {
+        self.textures.retain(|_, uploaded| uploaded.image.strong_count() > 0);
+
+        let textures = &self.textures;
+        self.descriptor_sets.retain(|key, _| textures.contains_key(key));
+    }
+
+    /// Returns the descriptor set binding a texture, uploading the texture
+    /// the first time it is seen. Uploads finish before the frame is drawn.
+    fn texture_set(&mut self, texture: &Texture, pipeline: &Arc<GraphicsPipeline>) -> Arc<PersistentDescriptorSet> {
+        if let Some(set) = self.descriptor_sets.get(texture.key()) {
+            return set.clone();
+        }
+
+        let view = match self.textures.get(texture.key()) {
+            Some(uploaded) => uploaded.view.clone(),
+            None => {
+                let (view, upload) = self.renderer.create_texture(texture.image());
+                let previous = self.previous_frame_end.take().unwrap();
+                self.previous_frame_end = Some(previous.join(upload).boxed());
+                self.textures.insert(texture.key().clone(), UploadedTexture {
+                    image: texture.downgrade(),
+                    view: view.clone(),
+                });
+                view
+            },
+        };
+
+        let layout = pipeline.layout().descriptor_set_layouts().first().unwrap();
+        let set = PersistentDescriptorSet::new(
+            layout.clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                view,
+                self.renderer.sampler.clone(),
+            )],
+        ).unwrap();
+
+        self.descriptor_sets.insert(texture.key().clone(), set.clone());
+        set
+    }
+
+    /// Submits the current frame and presents it, returning it read back if
+    /// a capture was requested
+    fn end_frame(&mut self) -> Option<Image> {
+        let Frame { mut builder, image_num, acquire_future } = self.frame.take()?;
+
         builder
             .end_render_pass()
             .unwrap();
 
+        // Requests are answered by this frame even if reading it back fails
+        let captures = std::mem::take(&mut self.captures);
+        let mut capture = None;
+        if !captures.is_empty() {
+            let buffer = self.renderer.create_capture_buffer();
+            match builder.copy_image_to_buffer(self.renderer.target.image(image_num), buffer.clone()) {
+                Ok(_) => capture = Some(buffer),
+                Err(e) => println!("Failed to capture frame: {}", e),
+            }
+        }
+
         let command_buffer = builder.build().unwrap();
 
-        let future = self.previous_frame_end
+        let mut future = self.previous_frame_end
             .take()
-            .unwrap()
-            .join(acquire_future)
-            .then_execute(self.renderer.queue.clone(), command_buffer)
-            .unwrap()
-            .then_swapchain_present(self.renderer.queue.clone(), self.renderer.swapchain.clone(), image_num)
-            .then_signal_fence_and_flush();
+            .unwrap();
+        if let Some(acquire_future) = acquire_future {
+            future = future.join(acquire_future).boxed();
+        }
 
-        match future {
+        let future = future
+            .then_execute(self.renderer.queue.clone(), command_buffer)
+            .unwrap();
+        let future = match &self.renderer.target {
+            Target::Window { swapchain, .. } => future
+                .then_swapchain_present(self.renderer.queue.clone(), swapchain.clone(), image_num)
+                .boxed(),
+            Target::Offscreen(_) => future.boxed(),
+        };
+
+        let mut image = None;
+        match future.then_signal_fence_and_flush() {
             Ok(future) => {
+                if let Some(buffer) = capture {
+                    match future.wait(None) {
+                        Ok(()) => image = self.renderer.read_back(&buffer),
+                        Err(e) => println!("Failed to capture frame: {:?}", e),
+                    }
+                }
                 self.previous_frame_end = Some(future.boxed());
             },
             Err(FlushError::OutOfDate) => {
@@ -209,5 +371,116 @@ impl System for RenderSystem {
                 self.previous_frame_end = Some(sync::now(self.renderer.device.clone()).boxed());
             }
         }
+
+        let image = image?;
+        for path in captures.iter().filter_map(|c| c.path.as_ref()) {
+            if let Err(e) = image.save_png(path) {
+                println!("Failed to save {}: {}", path.display(), e);
+            }
+        }
+
+        Some(image)
     }
-}
\ No newline at end of file
+}
+
+impl System for RenderSystem {
+    /// Draws the world as one pass of the frame. Paused scenes drawn under an
+    /// overlay share a frame with it, cleared by the first and presented by
+    /// the last.
+    fn run(&mut self, world: &mut World) {
+        let resize = self.resized.drain(&world.events::<Resized>());
+        if resize { self.resize() }
+
+        let pass = world.resource::<ScenePass>()
+            .map(|p| *p)
+            .unwrap_or_default();
+
+        let captures = world.events::<CaptureFrame>();
+        self.captures.extend(self.capture_frame.read(&captures).cloned());
+        drop(captures);
+
+        if pass.is_first() {
+            self.stats = RenderStats::default();
+            self.begin_frame();
+        }
+        self.draw(world);
+        if !pass.is_last() { return }
+
+        world.insert_resource(self.stats);
+
+        if let Some(image) = self.end_frame() {
+            world.send_event(FrameCaptured { image });
+        }
+    }
+}
+
+/// Gathers the world's sprites in the order they are drawn, adding their
+/// vertices to `vertices`.
+///
+/// Entities need a SpriteComponent and a PositionComponent in order to be
+/// drawn. Sprites are sorted back to front by layer and z, and by when their
+/// entities were spawned when level with each other, so overlapping sprites
+/// don't swap places as other entities come and go.
+fn collect_quads(world: &World, vertices: &mut Vec<Vertex>) -> Vec<Quad> {
+    let mut quads = vec![];
+
+    let layers = world.resource::<Layers>();
+    let layer_of = |name: &str| layers.as_ref().map_or(0, |l| l.index(name).unwrap_or(l.len()));
+    world.query::<(&PositionComponent, &SpriteComponent)>().for_each(|entity, (position, sprite)| {
+        quads.push(Quad {
+            layer: layer_of(&sprite.layer),
+            z: sprite.z,
+            order: world.spawn_order(entity).unwrap_or(u64::MAX),
+            shader: sprite.shader,
+            texture: match sprite.shader {
+                Shader::Texture => sprite.texture.clone(),
+                _ => None,
+            },
+            base_vertex: vertices.len() as u32,
+            indices: position.indices.clone(),
+        });
+        let color = sprite.vertex_color();
+        let [u0, v0, u1, v1] = sprite.uv_rect();
+        vertices.extend(position.vertices.iter().map(|v| Vertex {
+            color,
+            uv: [u0 + v.uv[0] * (u1 - u0), v0 + v.uv[1] * (v1 - v0)],
+            ..*v
+        }));
+    });
+
+    // Batching never reorders quads, it only merges neighbours
+    quads.sort_by(|a, b| a.layer.cmp(&b.layer)
+        .then(a.z.total_cmp(&b.z))
+        .then(a.order.cmp(&b.order)));
+    quads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    #[test]
+    fn draws_layers_back_to_front_and_keeps_spawn_order() {
+        let mut scene = Scene::from_xml(r#"<scene>
+            <layers><layer name="back"/><layer name="front"/></layers>
+            <entity id="a"><position x="0" y="0" width="1" height="1"/><sprite layer="front"/></entity>
+            <entity id="b"><position x="0" y="0" width="1" height="1"/><sprite layer="back" z="1"/></entity>
+            <entity id="c"><position x="0" y="0" width="1" height="1"/><sprite layer="front"/></entity>
+            <entity id="d"><position x="0" y="0" width="1" height="1"/><sprite layer="back"/></entity>
+            <entity id="e"><position x="0" y="0" width="1" height="1"/><sprite layer="front"/></entity>
+        </scene>"#).unwrap();
+
+        // Despawning moves other entities around in their storages
+        let b = scene.find("b").unwrap();
+        scene.world.despawn(b);
+
+        let world = &scene.world;
+        let order = |name: &str| world.find(name).and_then(|e| world.spawn_order(e));
+        let drawn: Vec<Option<u64>> = collect_quads(world, &mut vec![])
+            .iter()
+            .map(|q| Some(q.order))
+            .collect();
+        assert_eq!(drawn, ["d", "a", "c", "e"].map(order));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::hierarchy::{Children, Parent};
+use crate::systems::System;
+use crate::world::{EntityId, World};
+
+/// Moves child entities along with their parents. It runs right after
+/// physics, so collisions are checked where children are this step.
+pub struct TransformSystem { }
+
+impl TransformSystem {
+    pub fn new() -> Self {
+        TransformSystem {}
+    }
+}
+
+impl Default for TransformSystem {
+    fn default() -> Self {
+        TransformSystem::new()
+    }
+}
+
+impl System for TransformSystem {
+    fn run(&mut self, world: &mut World) {
+        // Walk down from every root so parents are always placed before their children
+        let roots: Vec<EntityId> = world.query::<&Children>()
+            .entities()
+            .into_iter()
+            .filter(|e| !world.has::<Parent>(*e))
+            .collect();
+
+        for root in roots {
+            world.place_children(root);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(feature = "bmp")]
+mod bmp;
+#[cfg(feature = "qoi")]
+mod qoi;
+#[cfg(feature = "tga")]
+mod tga;
+
+use crate::capture::Image;
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The widest or tallest image the loader accepts, the largest texture most
+/// GPUs can sample
+const MAX_SIDE: u32 = 16384;
+
+/// What identifies a texture, and its upload to the GPU
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum TextureKey {
+    /// Loaded from a file, shared by every texture loaded from the same path
+    Path(PathBuf),
+    /// Created from bytes in memory, unique to it and its clones
+    Memory(u64),
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    Png(png::DecodingError),
+    /// The data isn't in any format the loader recognises
+    UnknownFormat,
+    /// The data is in a format whose cargo feature isn't enabled
+    Disabled(&'static str),
+    /// The data is malformed, or uses a variant of its format that isn't
+    /// supported
+    Invalid(&'static str),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "{}", e),
+            TextureError::Png(e) => write!(f, "{}", e),
+            TextureError::UnknownFormat => write!(f, "Unknown image format"),
+            TextureError::Disabled(feature) => write!(f, "Enable the \"{}\" feature to load this image", feature),
+            TextureError::Invalid(reason) => write!(f, "Invalid image: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(e) => Some(e),
+            TextureError::Png(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TextureError {
+    fn from(e: io::Error) -> Self {
+        TextureError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for TextureError {
+    fn from(e: png::DecodingError) -> Self {
+        TextureError::Png(e)
+    }
+}
+
+/// An image decoded to RGBA8, cheap to clone and shared between sprites.
+///
+/// PNG is always supported. QOI, BMP and TGA are behind the cargo features
+/// of the same names.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    key: TextureKey,
+    image: Arc<Image>,
+}
+
+impl Texture {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TextureError> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+
+        Ok(Texture {
+            key: TextureKey::Path(path.to_path_buf()),
+            image: Arc::new(decode(&data)?),
+        })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TextureError> {
+        Ok(Texture::from_image(decode(data)?))
+    }
+
+    pub fn from_image(image: Image) -> Self {
+        Texture {
+            key: TextureKey::Memory(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            image: Arc::new(image),
+        }
+    }
+
+    pub fn key(&self) -> &TextureKey {
+        &self.key
+    }
+
+    /// The file the texture was loaded from, if any
+    pub fn path(&self) -> Option<&Path> {
+        match &self.key {
+            TextureKey::Path(path) => Some(path),
+            TextureKey::Memory(_) => None,
+        }
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// A handle that stops upgrading once every clone of the texture is gone
+    pub(crate) fn downgrade(&self) -> Weak<Image> {
+        Arc::downgrade(&self.image)
+    }
+}
+
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+/// Decodes an image to RGBA8, telling the format apart by its signature.
+/// TGA has none, so anything unrecognised is tried as TGA when it's enabled.
+pub fn decode(data: &[u8]) -> Result<Image, TextureError> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        decode_png(data)
+    } else if data.starts_with(b"qoif") {
+        decode_qoi(data)
+    } else if data.starts_with(b"BM") {
+        decode_bmp(data)
+    } else {
+        decode_tga(data)
+    }
+}
+
+/// Checks the size an image's header gives, returning how many pixels it has
+fn pixel_count(width: u32, height: u32) -> Result<usize, TextureError> {
+    if width == 0 || height == 0 {
+        return Err(TextureError::Invalid("image has no pixels"));
+    }
+    if width > MAX_SIDE || height > MAX_SIDE {
+        return Err(TextureError::Invalid("image is too large"));
+    }
+
+    (width as usize).checked_mul(height as usize)
+        .ok_or(TextureError::Invalid("image is too large"))
+}
+
+#[cfg(feature = "qoi")]
+use self::qoi::decode as decode_qoi;
+
+#[cfg(not(feature = "qoi"))]
+fn decode_qoi(_: &[u8]) -> Result<Image, TextureError> {
+    Err(TextureError::Disabled("qoi"))
+}
+
+#[cfg(feature = "bmp")]
+use self::bmp::decode as decode_bmp;
+
+#[cfg(not(feature = "bmp"))]
+fn decode_bmp(_: &[u8]) -> Result<Image, TextureError> {
+    Err(TextureError::Disabled("bmp"))
+}
+
+#[cfg(feature = "tga")]
+use self::tga::decode as decode_tga;
+
+#[cfg(not(feature = "tga"))]
+fn decode_tga(_: &[u8]) -> Result<Image, TextureError> {
+    Err(TextureError::UnknownFormat)
+}
+
+/// Expands palettes, transparency chunks and low bit depths and strips 16-bit
+/// channels, so every PNG comes out as 8-bit gray, gray and alpha, RGB or
+/// RGBA, which is then widened to RGBA
+fn decode_png(data: &[u8]) -> Result<Image, TextureError> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    if info.bit_depth != png::BitDepth::Eight {
+        return Err(TextureError::Invalid("unexpected PNG bit depth"));
+    }
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(TextureError::Invalid("unexpanded PNG palette")),
+    };
+
+    let mut rgba = Vec::with_capacity(pixel_count(info.width, info.height)? * 4);
+    for row in buffer.chunks_exact(info.line_size) {
+        let row = &row[..(info.width * channels) as usize];
+        for pixel in row.chunks_exact(channels as usize) {
+            rgba.extend_from_slice(&match *pixel {
+                [l] => [l, l, l, 255],
+                [l, a] => [l, l, l, a],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            });
+        }
+    }
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        data: rgba,
+    })
+}
@@ -0,0 +1,178 @@
+use crate::capture::Image;
+use crate::texture::{pixel_count, TextureError};
+
+const FILE_HEADER_SIZE: usize = 14;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16, TextureError> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(TextureError::Invalid("truncated BMP data"))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32, TextureError> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(TextureError::Invalid("truncated BMP data"))
+}
+
+/// Extracts the channel selected by `mask` from a pixel, scaled to 8 bits
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 { return 255 }
+
+    let value = ((pixel & mask) >> mask.trailing_zeros()) as u64;
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    (value * 255 / max) as u8
+}
+
+/// Decodes an uncompressed BMP with 8-bit palettes or 24- or 32-bit pixels
+pub fn decode(data: &[u8]) -> Result<Image, TextureError> {
+    let offset = u32_at(data, 10)? as usize;
+    let header_size = u32_at(data, FILE_HEADER_SIZE)? as usize;
+    if header_size < 40 {
+        return Err(TextureError::Invalid("unsupported BMP header"));
+    }
+
+    let width = u32_at(data, 18)? as i32;
+    let height = u32_at(data, 22)? as i32;
+    let bits = u16_at(data, 28)?;
+    let compression = u32_at(data, 30)?;
+    let colors_used = u32_at(data, 46)?;
+
+    if width <= 0 || height == 0 {
+        return Err(TextureError::Invalid("BMP has no pixels"));
+    }
+
+    // Rows are stored bottom-up unless the height is negative
+    let top_down = height < 0;
+    let width = width as u32;
+    let height = height.unsigned_abs();
+
+    let masks = match (bits, compression) {
+        (8, BI_RGB) | (24, BI_RGB) | (32, BI_RGB) => [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0],
+        (32, BI_BITFIELDS) => {
+            // Masks follow the header, alpha only in the larger headers
+            let at = FILE_HEADER_SIZE + 40;
+            let alpha = match header_size >= 56 {
+                true => u32_at(data, at + 12)?,
+                false => 0,
+            };
+            [u32_at(data, at)?, u32_at(data, at + 4)?, u32_at(data, at + 8)?, alpha]
+        },
+        _ => return Err(TextureError::Invalid("unsupported BMP pixel format")),
+    };
+
+    let palette = match bits {
+        8 => {
+            let count = match colors_used {
+                0 => 256,
+                n => n as usize,
+            };
+            let start = FILE_HEADER_SIZE + header_size;
+            let palette = data.get(start..start + count * 4)
+                .ok_or(TextureError::Invalid("truncated BMP data"))?;
+            palette.chunks_exact(4)
+                .map(|c| [c[2], c[1], c[0], 255])
+                .collect()
+        },
+        _ => vec![],
+    };
+
+    // Every row has to be there before the image is allocated
+    let pixels = pixel_count(width, height)?;
+    let bytes_per_pixel = bits as usize / 8;
+    let row_size = width as usize * bytes_per_pixel;
+    let stride = (row_size + 3) & !3;
+    let end = stride.checked_mul(height as usize - 1)
+        .and_then(|n| n.checked_add(offset))
+        .and_then(|n| n.checked_add(row_size));
+    if end.is_none_or(|end| end > data.len()) {
+        return Err(TextureError::Invalid("truncated BMP data"));
+    }
+
+    let mut rgba = Vec::with_capacity(pixels * 4);
+
+    for y in 0..height as usize {
+        let row = match top_down {
+            true => y,
+            false => height as usize - 1 - y,
+        };
+        let start = offset + row * stride;
+        let row = data.get(start..start + row_size)
+            .ok_or(TextureError::Invalid("truncated BMP data"))?;
+
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            let color = match *pixel {
+                [i] => *palette.get(i as usize)
+                    .ok_or(TextureError::Invalid("BMP palette index out of range"))?,
+                [b, g, r] => [r, g, b, 255],
+                [b0, b1, b2, b3] => {
+                    let p = u32::from_le_bytes([b0, b1, b2, b3]);
+                    [channel(p, masks[0]), channel(p, masks[1]), channel(p, masks[2]), channel(p, masks[3])]
+                },
+                _ => unreachable!(),
+            };
+            rgba.extend_from_slice(&color);
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        data: rgba,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bottom-up BMP with a 40-byte header, `pixels` following the headers
+    fn bmp(width: i32, height: i32, bits: u16, compression: u32, pixels: &[u8]) -> Vec<u8> {
+        let offset = (FILE_HEADER_SIZE + 40) as u32;
+        let mut data = b"BM".to_vec();
+        data.extend_from_slice(&(offset + pixels.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&40u32.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bits.to_le_bytes());
+        data.extend_from_slice(&compression.to_le_bytes());
+        data.extend_from_slice(&[0; 20]);
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    #[test]
+    fn decodes_rows_bottom_up() {
+        let image = decode(&bmp(1, 2, 24, BI_RGB, &[1, 2, 3, 0, 4, 5, 6, 0])).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert_eq!(image.data, [6, 5, 4, 255, 3, 2, 1, 255]);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let data = bmp(1, 1, 24, BI_RGB, &[0; 4]);
+        for len in [0, 2, 14, 20, 40] {
+            assert!(decode(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_sizes_the_data_cannot_hold() {
+        assert!(decode(&bmp(0x7fff_ffff, 0x7fff_ffff, 32, BI_RGB, &[0; 4])).is_err());
+        assert!(decode(&bmp(16384, 16384, 32, BI_RGB, &[0; 4])).is_err());
+        assert!(decode(&bmp(2, 2, 24, BI_RGB, &[0; 8])).is_err());
+        assert!(decode(&bmp(0, 0, 24, BI_RGB, &[])).is_err());
+    }
+
+    #[test]
+    fn scales_wide_masks() {
+        assert_eq!(channel(0xffff_ffff, 0xffff_ffff), 255);
+        assert_eq!(channel(0x8000_0000, 0xffff_ffff), 127);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::capture::Image;
+use crate::texture::{pixel_count, TextureError};
+
+const HEADER_SIZE: usize = 14;
+
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+
+/// The most pixels one byte of data can stand for, in a run
+const MAX_RUN: usize = 62;
+
+/// Decodes a QOI image, see https://qoiformat.org/qoi-specification.pdf
+pub fn decode(data: &[u8]) -> Result<Image, TextureError> {
+    let truncated = TextureError::Invalid("truncated QOI data");
+    if data.len() < HEADER_SIZE { return Err(truncated) }
+
+    let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    let pixels = pixel_count(width, height)?;
+    if pixels > (data.len() - HEADER_SIZE) * MAX_RUN {
+        return Err(truncated);
+    }
+
+    // Grown as pixels are decoded, so a lying header can't reserve much
+    let mut rgba = vec![];
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0, 0, 0, 255];
+    let mut bytes = data[HEADER_SIZE..].iter().copied();
+    let mut next = || bytes.next().ok_or(TextureError::Invalid("truncated QOI data"));
+
+    while rgba.len() < pixels * 4 {
+        let op = next()?;
+        let mut run = 1;
+
+        match op {
+            OP_RGB => {
+                pixel[0] = next()?;
+                pixel[1] = next()?;
+                pixel[2] = next()?;
+            },
+            OP_RGBA => {
+                pixel = [next()?, next()?, next()?, next()?];
+            },
+            _ => match op & 0xc0 {
+                OP_INDEX => pixel = index[op as usize],
+                OP_DIFF => {
+                    pixel[0] = pixel[0].wrapping_add((op >> 4) & 3).wrapping_sub(2);
+                    pixel[1] = pixel[1].wrapping_add((op >> 2) & 3).wrapping_sub(2);
+                    pixel[2] = pixel[2].wrapping_add(op & 3).wrapping_sub(2);
+                },
+                OP_LUMA => {
+                    let second = next()?;
+                    let dg = (op & 0x3f).wrapping_sub(32);
+                    pixel[0] = pixel[0].wrapping_add(dg).wrapping_sub(8).wrapping_add(second >> 4);
+                    pixel[1] = pixel[1].wrapping_add(dg);
+                    pixel[2] = pixel[2].wrapping_add(dg).wrapping_sub(8).wrapping_add(second & 0x0f);
+                },
+                OP_RUN => run = (op & 0x3f) as usize + 1,
+                _ => unreachable!(),
+            },
+        }
+
+        let [r, g, b, a] = pixel;
+        let hash = (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64;
+        index[hash] = pixel;
+
+        for _ in 0..run.min(pixels - rgba.len() / 4) {
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        data: rgba,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"qoif".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[4, 0]);
+        data
+    }
+
+    #[test]
+    fn decodes_runs() {
+        let mut data = header(3, 1);
+        data.extend_from_slice(&[OP_RGBA, 1, 2, 3, 4, OP_RUN | 1]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.data, [1, 2, 3, 4].repeat(3));
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let data = header(1, 1);
+        for len in 0..data.len() {
+            assert!(decode(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_sizes_the_data_cannot_hold() {
+        assert!(decode(&header(u32::MAX, u32::MAX)).is_err());
+        assert!(decode(&header(16384, 16384)).is_err());
+        assert!(decode(&header(0, 0)).is_err());
+
+        let mut data = header(100, 1);
+        data.push(OP_RUN | 61);
+        assert!(decode(&data).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::capture::Image;
+use crate::texture::{pixel_count, TextureError};
+
+const HEADER_SIZE: usize = 18;
+
+const TRUE_COLOR: u8 = 2;
+const GRAYSCALE: u8 = 3;
+const RLE_TRUE_COLOR: u8 = 10;
+const RLE_GRAYSCALE: u8 = 11;
+
+/// The most pixels a run-length packet can stand for
+const MAX_RUN: usize = 128;
+
+/// Reads one BGR(A) or gray pixel as RGBA
+fn read_pixel(bytes: &mut impl Iterator<Item = u8>, bytes_per_pixel: usize) -> Result<[u8; 4], TextureError> {
+    let mut next = || bytes.next().ok_or(TextureError::Invalid("truncated TGA data"));
+
+    Ok(match bytes_per_pixel {
+        1 => {
+            let l = next()?;
+            [l, l, l, 255]
+        },
+        3 => {
+            let (b, g, r) = (next()?, next()?, next()?);
+            [r, g, b, 255]
+        },
+        _ => {
+            let (b, g, r, a) = (next()?, next()?, next()?, next()?);
+            [r, g, b, a]
+        },
+    })
+}
+
+/// Decodes a true-color or grayscale TGA, raw or run-length encoded
+pub fn decode(data: &[u8]) -> Result<Image, TextureError> {
+    if data.len() < HEADER_SIZE {
+        return Err(TextureError::UnknownFormat);
+    }
+
+    let id_length = data[0] as usize;
+    let color_map_type = data[1];
+    let image_type = data[2];
+    let color_map_length = u16::from_le_bytes([data[5], data[6]]) as usize;
+    let color_map_bits = data[7] as usize;
+    let width = u16::from_le_bytes([data[12], data[13]]) as u32;
+    let height = u16::from_le_bytes([data[14], data[15]]) as u32;
+    let bits = data[16];
+    let top_down = data[17] & 0x20 != 0;
+
+    let (rle, gray) = match image_type {
+        TRUE_COLOR => (false, false),
+        GRAYSCALE => (false, true),
+        RLE_TRUE_COLOR => (true, false),
+        RLE_GRAYSCALE => (true, true),
+        1 | 9 => return Err(TextureError::Invalid("color-mapped TGA images aren't supported")),
+        _ => return Err(TextureError::UnknownFormat),
+    };
+
+    let bytes_per_pixel = match (gray, bits) {
+        (true, 8) => 1,
+        (false, 24) => 3,
+        (false, 32) => 4,
+        _ => return Err(TextureError::Invalid("unsupported TGA pixel depth")),
+    };
+
+    // A color map can be present even when the image doesn't use it
+    let mut start = HEADER_SIZE + id_length;
+    if color_map_type == 1 {
+        start += color_map_length * color_map_bits.div_ceil(8);
+    }
+    let body = data.get(start..)
+        .ok_or(TextureError::Invalid("truncated TGA data"))?;
+
+    // Anything unrecognised is tried as TGA, so the header can be arbitrary
+    // bytes and its size isn't trusted until the data could hold it
+    let pixels = pixel_count(width, height)?;
+    let most = match rle {
+        true => body.len() / (1 + bytes_per_pixel) * MAX_RUN,
+        false => body.len() / bytes_per_pixel,
+    };
+    if pixels > most {
+        return Err(TextureError::Invalid("truncated TGA data"));
+    }
+
+    let mut bytes = body.iter().copied();
+    let mut decoded = vec![];
+    while decoded.len() < pixels {
+        if !rle {
+            decoded.push(read_pixel(&mut bytes, bytes_per_pixel)?);
+            continue;
+        }
+
+        let packet = bytes.next().ok_or(TextureError::Invalid("truncated TGA data"))?;
+        let count = (packet & 0x7f) as usize + 1;
+        if packet & 0x80 != 0 {
+            let pixel = read_pixel(&mut bytes, bytes_per_pixel)?;
+            decoded.extend(std::iter::repeat_n(pixel, count));
+        } else {
+            for _ in 0..count {
+                decoded.push(read_pixel(&mut bytes, bytes_per_pixel)?);
+            }
+        }
+    }
+    decoded.truncate(pixels);
+
+    // Rows are stored bottom-up unless the descriptor says otherwise
+    let mut rgba = Vec::with_capacity(pixels * 4);
+    for y in 0..height as usize {
+        let row = match top_down {
+            true => y,
+            false => height as usize - 1 - y,
+        };
+        for pixel in &decoded[row * width as usize..(row + 1) * width as usize] {
+            rgba.extend_from_slice(pixel);
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        data: rgba,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(image_type: u8, width: u16, height: u16, bits: u8) -> Vec<u8> {
+        let mut data = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&[bits, 0]);
+        data
+    }
+
+    #[test]
+    fn decodes_run_length_packets() {
+        let mut data = header(RLE_TRUE_COLOR, 2, 2, 24);
+        data.extend_from_slice(&[0x83, 1, 2, 3]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.data, [3, 2, 1, 255].repeat(4));
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let data = header(TRUE_COLOR, 1, 1, 24);
+        for len in 0..data.len() {
+            assert!(decode(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_sizes_the_data_cannot_hold() {
+        assert!(decode(&header(TRUE_COLOR, u16::MAX, u16::MAX, 32)).is_err());
+        assert!(decode(&header(RLE_TRUE_COLOR, u16::MAX, u16::MAX, 32)).is_err());
+        assert!(decode(&header(TRUE_COLOR, 0, 0, 24)).is_err());
+
+        let mut data = header(TRUE_COLOR, 2, 1, 24);
+        data.extend_from_slice(&[1, 2, 3]);
+        assert!(decode(&data).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+/// Timing information for the systems of the current frame.
+///
+/// Simulation stages run at a fixed rate, possibly several times per frame, and
+/// see `delta == fixed_delta`. The render stage runs once per frame and sees
+/// the real time since the last frame, along with how far the simulation has
+/// progressed towards its next step in `alpha`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Time {
+    pub delta: Duration,
+    pub frame_delta: Duration,
+    pub fixed_delta: Duration,
+    pub elapsed: Duration,
+    pub frame_count: u64,
+    pub alpha: f32,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Time {
+            delta: Duration::ZERO,
+            frame_delta: Duration::ZERO,
+            fixed_delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            frame_count: 0,
+            alpha: 0.0,
+        }
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time::new()
+    }
+}
+
+/// Accumulates real time and hands it out in steps of a fixed size
+pub struct FixedTimestep {
+    step: Duration,
+    /// Caps the steps run per frame so a slow frame can't snowball
+    pub max_steps: u32,
+    accumulator: Duration,
+    steps: u32,
+}
+
+impl FixedTimestep {
+    /// Panics if `step` is zero
+    pub fn new(step: Duration) -> Self {
+        assert!(!step.is_zero(), "fixed step must be longer than zero");
+        FixedTimestep {
+            step,
+            max_steps: 8,
+            accumulator: Duration::ZERO,
+            steps: 0,
+        }
+    }
+
+    /// Panics if `hz` is 0
+    pub fn from_rate(hz: u32) -> Self {
+        FixedTimestep::new(Duration::from_secs(1).checked_div(hz).unwrap_or_default())
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn accumulate(&mut self, delta: Duration) {
+        self.accumulator += delta;
+        self.steps = 0;
+    }
+
+    /// Takes one step out of the accumulated time, if there is enough of it
+    pub fn expend(&mut self) -> bool {
+        if self.accumulator < self.step { return false }
+
+        if self.steps >= self.max_steps {
+            // Drop the backlog instead of falling further behind
+            self.accumulator = Duration::from_nanos(
+                (self.accumulator.as_nanos() % self.step.as_nanos()) as u64
+            );
+            return false;
+        }
+
+        self.accumulator -= self.step;
+        self.steps += 1;
+        true
+    }
+
+    /// The fraction of a step left over in the accumulator
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "fixed step")]
+    fn rejects_a_step_of_zero() {
+        FixedTimestep::from_rate(0);
+    }
+
+    #[test]
+    fn drops_the_backlog_after_too_many_steps() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10));
+        timestep.accumulate(Duration::from_millis(205));
+
+        let steps = std::iter::from_fn(|| timestep.expend().then_some(())).count();
+        assert_eq!(steps, 8);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-3);
+    }
+}
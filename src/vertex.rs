@@ -1,6 +1,8 @@
 #[derive(Default, Copy, Clone, PartialEq)]
+#[repr(C)]
 pub struct Vertex {
     pub position: [f32; 2],
-    pub color: [f32; 3],
+    /// Straight (not premultiplied) RGBA from 0 to 1
+    pub color: [f32; 4],
     pub uv: [f32; 2]
 }
\ No newline at end of file
@@ -0,0 +1,635 @@
+pub mod allocator;
+pub mod query;
+pub mod storage;
+
+use crate::components::Component;
+use crate::components::animation::AnimationComponent;
+use crate::components::audio::AudioComponent;
+use crate::components::camera::CameraComponent;
+use crate::components::collision::CollisionComponent;
+use crate::components::custom::CustomComponent;
+use crate::components::moveable::MoveableComponent;
+use crate::components::physics::PhysicsComponent;
+use crate::components::position::PositionComponent;
+use crate::components::sprite::SpriteComponent;
+use crate::commands::Commands;
+use crate::entity::{Entity, Name};
+use crate::events::Events;
+use crate::hierarchy::{Children, Parent};
+use crate::world::allocator::EntityAllocator;
+use crate::world::query::{Fetch, Query};
+use crate::world::storage::SparseSet;
+
+use std::any::{type_name, Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Copies one type of custom component off an entity, see `World::snapshot`
+type Snapshot = fn(&World, EntityId) -> Option<Component>;
+
+/// A handle to an entity in a `World`.
+///
+/// The index is reused once the entity is despawned, the generation is not,
+/// so stale handles never resolve to a newer entity.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        EntityId {
+            index,
+            generation,
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// Returned when operating on an entity that was despawned or never existed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NoSuchEntity(pub EntityId);
+
+impl fmt::Display for NoSuchEntity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Entity {} does not exist", self.0)
+    }
+}
+
+impl std::error::Error for NoSuchEntity {}
+
+trait Storage {
+    fn remove_entity(&mut self, entity: EntityId);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> Storage for RefCell<SparseSet<T>> {
+    fn remove_entity(&mut self, entity: EntityId) {
+        self.get_mut().remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Owns every entity in a scene and stores their components by type.
+///
+/// Each component type lives in its own sparse set behind a `RefCell`, so
+/// systems can hold several queries at once as long as they don't borrow the
+/// same component type mutably twice. Conflicting borrows panic when the
+/// query is created. Resources, single values not tied to any entity such as
+/// event queues, are borrowed the same way.
+pub struct World {
+    entities: EntityAllocator,
+    names: HashMap<String, EntityId>,
+    storages: HashMap<TypeId, Box<dyn Storage>>,
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    event_updates: Vec<fn(&World)>,
+    custom_components: Vec<(TypeId, Snapshot)>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            entities: EntityAllocator::new(),
+            names: HashMap::new(),
+            storages: HashMap::new(),
+            resources: HashMap::new(),
+            event_updates: vec![],
+            custom_components: vec![],
+        }
+    }
+
+    pub fn spawn(&mut self, components: Vec<Component>) -> EntityId {
+        let entity = self.entities.allocate();
+
+        for component in components {
+            self.add_component(entity, component)
+                .expect("Freshly spawned entity is missing");
+        }
+
+        entity
+    }
+
+    /// Spawns an entity along with its name and children, if it has any
+    pub fn spawn_entity(&mut self, entity: Entity) -> EntityId {
+        let id = self.spawn(entity.components);
+        if let Some(name) = entity.name {
+            self.set_name(id, &name)
+                .expect("Freshly spawned entity is missing");
+        }
+
+        for child in entity.children {
+            let child = self.spawn_entity(child);
+            self.set_parent(child, id)
+                .expect("Freshly spawned entity is missing");
+        }
+
+        id
+    }
+
+    /// Copies an entity and its descendants back out of the world. Children's
+    /// positions are given relative to their parent, as in scene XML.
+    pub fn snapshot(&self, entity: EntityId) -> Option<Entity> {
+        if !self.is_alive(entity) { return None }
+
+        let mut components = vec![];
+        if let Some(c) = self.get::<AnimationComponent>(entity) {
+            components.push(Component::Animation(c.clone()));
+        }
+        if let Some(c) = self.get::<AudioComponent>(entity) {
+            components.push(Component::Audio(c.clone()));
+        }
+        if let Some(c) = self.get::<CameraComponent>(entity) {
+            components.push(Component::Camera(c.clone()));
+        }
+        if let Some(c) = self.get::<CollisionComponent>(entity) {
+            components.push(Component::Collision(c.clone()));
+        }
+        if let Some(c) = self.get::<MoveableComponent>(entity) {
+            components.push(Component::Moveable(c.clone()));
+        }
+        if let Some(c) = self.get::<PhysicsComponent>(entity) {
+            components.push(Component::Physics(c.clone()));
+        }
+        if let Some(c) = self.get::<PositionComponent>(entity) {
+            let mut c = c.clone();
+            if let Some(parent) = self.get::<Parent>(entity) {
+                c.set_position(parent.offset);
+            }
+            components.push(Component::Position(c));
+        }
+        if let Some(c) = self.get::<SpriteComponent>(entity) {
+            components.push(Component::Sprite(c.clone()));
+        }
+        for (_, snapshot) in self.custom_components.iter() {
+            components.extend(snapshot(self, entity));
+        }
+
+        Some(Entity {
+            name: self.get::<Name>(entity).map(|n| n.0.clone()),
+            components,
+            children: self.children(entity)
+                .into_iter()
+                .filter_map(|c| self.snapshot(c))
+                .collect(),
+        })
+    }
+
+    /// Removes the entity, its descendants and all of their components,
+    /// returning false if the handle was already stale
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        if !self.is_alive(entity) { return false }
+
+        self.remove_parent(entity);
+        for descendant in self.descendants(entity) {
+            self.despawn_single(descendant);
+        }
+        self.despawn_single(entity)
+    }
+
+    fn despawn_single(&mut self, entity: EntityId) -> bool {
+        if !self.entities.deallocate(entity) { return false }
+
+        self.forget_name(entity);
+
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+
+        true
+    }
+
+    /// Attaches `child` to `parent`. The child's current position becomes its
+    /// offset from the parent, and the child and its descendants are moved
+    /// into place right away. Attaching an entity to one of its own
+    /// descendants is ignored.
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> Result<(), NoSuchEntity> {
+        if !self.is_alive(child) { return Err(NoSuchEntity(child)) }
+        if !self.is_alive(parent) { return Err(NoSuchEntity(parent)) }
+        if child == parent || self.descendants(child).contains(&parent) { return Ok(()) }
+
+        self.remove_parent(child);
+
+        let offset = self.get::<PositionComponent>(child)
+            .map_or((0.0, 0.0), |p| p.position);
+        self.insert(child, Parent {
+            entity: parent,
+            offset,
+        })?;
+
+        let added = self.get_mut::<Children>(parent)
+            .map(|mut children| children.0.push(child))
+            .is_some();
+        if !added {
+            self.insert(parent, Children(vec![child]))?;
+        }
+
+        let origin = self.get::<PositionComponent>(parent)
+            .map_or((0.0, 0.0), |p| p.position);
+        self.place(child, origin);
+
+        Ok(())
+    }
+
+    /// Moves the descendants of `entity` to their offsets from it, parents
+    /// before their children
+    pub(crate) fn place_children(&self, entity: EntityId) {
+        let origin = self.get::<PositionComponent>(entity)
+            .map_or((0.0, 0.0), |p| p.position);
+
+        for child in self.children(entity) {
+            self.place(child, origin);
+        }
+    }
+
+    fn place(&self, child: EntityId, origin: (f32, f32)) {
+        let offset = match self.get::<Parent>(child) {
+            Some(parent) => parent.offset,
+            None => return,
+        };
+
+        let position = (origin.0 + offset.0, origin.1 + offset.1);
+
+        if let Some(mut c) = self.get_mut::<PositionComponent>(child) {
+            c.set_position(position);
+        }
+
+        for grandchild in self.children(child) {
+            self.place(grandchild, position);
+        }
+    }
+
+    /// Detaches the entity from its parent, leaving it where it is
+    pub fn remove_parent(&mut self, child: EntityId) {
+        let parent = match self.remove::<Parent>(child) {
+            Some(p) => p.entity,
+            None => return,
+        };
+
+        if let Some(mut children) = self.get_mut::<Children>(parent) {
+            children.0.retain(|c| *c != child);
+        }
+    }
+
+    pub fn parent(&self, entity: EntityId) -> Option<EntityId> {
+        self.get::<Parent>(entity).map(|p| p.entity)
+    }
+
+    pub fn children(&self, entity: EntityId) -> Vec<EntityId> {
+        self.get::<Children>(entity)
+            .map_or(vec![], |c| c.0.clone())
+    }
+
+    /// Lists the entity's children, their children and so on, parents first
+    pub fn descendants(&self, entity: EntityId) -> Vec<EntityId> {
+        let mut descendants = self.children(entity);
+        let mut i = 0;
+        while i < descendants.len() {
+            descendants.extend(self.children(descendants[i]));
+            i += 1;
+        }
+
+        descendants
+    }
+
+    /// Gives the entity a name it can be looked up by with `find`, replacing
+    /// any name it had before. Names are unique, so an entity already going
+    /// by the name loses it.
+    pub fn set_name(&mut self, entity: EntityId, name: &str) -> Result<(), NoSuchEntity> {
+        if !self.is_alive(entity) { return Err(NoSuchEntity(entity)) }
+
+        self.forget_name(entity);
+        if let Some(previous) = self.names.insert(name.to_string(), entity) {
+            self.remove::<Name>(previous);
+        }
+
+        self.insert(entity, Name(name.to_string()))
+    }
+
+    pub fn find(&self, name: &str) -> Option<EntityId> {
+        self.names.get(name)
+            .copied()
+            .filter(|e| self.is_alive(*e))
+    }
+
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    /// A number that grows with every spawn, for ordering entities by when
+    /// they were spawned. `None` if the entity is gone.
+    pub fn spawn_order(&self, entity: EntityId) -> Option<u64> {
+        self.entities.spawn_order(entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entities.iter()
+    }
+
+    pub fn add_component(&mut self, entity: EntityId, component: Component) -> Result<(), NoSuchEntity> {
+        match component {
+            Component::Animation(c) => self.insert(entity, c),
+            Component::Audio(c) => self.insert(entity, c),
+            Component::Camera(c) => self.insert(entity, c),
+            Component::Collision(c) => self.insert(entity, c),
+            Component::Moveable(c) => self.insert(entity, c),
+            Component::Physics(c) => self.insert(entity, c),
+            Component::Position(c) => self.insert(entity, c),
+            Component::Sprite(c) => self.insert(entity, c),
+            Component::Custom(c) => c.insert(self, entity),
+        }
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: EntityId, component: T) -> Result<(), NoSuchEntity> {
+        if !self.is_alive(entity) { return Err(NoSuchEntity(entity)) }
+
+        self.storage_mut::<T>().insert(entity, component);
+        Ok(())
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: EntityId) -> Option<T> {
+        self.storages.get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<RefCell<SparseSet<T>>>()?
+            .get_mut()
+            .remove(entity)
+    }
+
+    pub fn has<T: 'static>(&self, entity: EntityId) -> bool {
+        self.borrow_storage::<T>()
+            .is_some_and(|s| s.contains(entity))
+    }
+
+    pub fn get<T: 'static>(&self, entity: EntityId) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.borrow_storage::<T>()?, |s| s.get(entity)).ok()
+    }
+
+    pub fn get_mut<T: 'static>(&self, entity: EntityId) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.borrow_storage_mut::<T>()?, |s| s.get_mut(entity)).ok()
+    }
+
+    /// Mutably borrows the same component type on two different entities
+    pub fn get_pair_mut<T: 'static>(&self, a: EntityId, b: EntityId) -> Option<(RefMut<'_, T>, RefMut<'_, T>)> {
+        let storage = self.borrow_storage_mut::<T>()?;
+        if a == b || !(storage.contains(a) && storage.contains(b)) { return None }
+
+        Some(RefMut::map_split(storage, |s| {
+            s.get_pair_mut(a, b).expect("Entities vanished from storage")
+        }))
+    }
+
+    /// Borrows component storages for iteration, e.g.
+    /// `world.query::<(&PositionComponent, &mut PhysicsComponent)>()`
+    pub fn query<Q: Fetch>(&self) -> Query<'_, Q> {
+        Query::new(self)
+    }
+
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+        self.resources.insert(TypeId::of::<T>(), Box::new(RefCell::new(resource)));
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        resource.downcast::<RefCell<T>>()
+            .ok()
+            .map(|r| r.into_inner())
+    }
+
+    pub fn has_resource<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn resource<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        self.resource_cell::<T>().map(|r| {
+            r.try_borrow()
+                .unwrap_or_else(|_| panic!("{} is already borrowed mutably", type_name::<T>()))
+        })
+    }
+
+    pub fn resource_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        self.resource_cell::<T>().map(|r| {
+            r.try_borrow_mut()
+                .unwrap_or_else(|_| panic!("{} is already borrowed", type_name::<T>()))
+        })
+    }
+
+    /// Lets `snapshot` include a custom component type. Types added through
+    /// `Component::custom` are registered automatically.
+    pub fn register_component<T: CustomComponent>(&mut self) {
+        let id = TypeId::of::<T>();
+        if self.custom_components.iter().any(|(t, _)| *t == id) { return }
+
+        self.custom_components.push((id, snapshot_component::<T>));
+    }
+
+    /// Registers an event type so it can be sent and read, see `Events`
+    pub fn add_event<T: 'static>(&mut self) {
+        if self.has_resource::<Events<T>>() { return }
+
+        self.insert_resource(Events::<T>::new());
+        self.event_updates.push(update_events::<T>);
+    }
+
+    /// Borrows the queue of deferred changes, see `Commands`
+    pub fn commands(&self) -> RefMut<'_, Commands> {
+        self.resource_mut::<Commands>()
+            .expect("World has no command queue")
+    }
+
+    pub fn events<T: 'static>(&self) -> Ref<'_, Events<T>> {
+        self.resource::<Events<T>>()
+            .unwrap_or_else(|| panic!("Event {} was never added to the world", type_name::<T>()))
+    }
+
+    pub fn send_event<T: 'static>(&self, event: T) {
+        self.resource_mut::<Events<T>>()
+            .unwrap_or_else(|| panic!("Event {} was never added to the world", type_name::<T>()))
+            .send(event);
+    }
+
+    /// Advances every event queue by one frame
+    pub fn update_events(&self) {
+        for update in self.event_updates.iter() {
+            update(self);
+        }
+    }
+
+    pub(crate) fn borrow_storage<T: 'static>(&self) -> Option<Ref<'_, SparseSet<T>>> {
+        self.storage::<T>().map(|s| {
+            s.try_borrow()
+                .unwrap_or_else(|_| panic!("{} is already borrowed mutably", type_name::<T>()))
+        })
+    }
+
+    pub(crate) fn borrow_storage_mut<T: 'static>(&self) -> Option<RefMut<'_, SparseSet<T>>> {
+        self.storage::<T>().map(|s| {
+            s.try_borrow_mut()
+                .unwrap_or_else(|_| panic!("{} is already borrowed", type_name::<T>()))
+        })
+    }
+
+    fn forget_name(&mut self, entity: EntityId) {
+        if let Some(Name(name)) = self.remove::<Name>(entity) {
+            // Another entity may have taken over the name since
+            if self.names.get(&name) == Some(&entity) {
+                self.names.remove(&name);
+            }
+        }
+    }
+
+    fn resource_cell<T: 'static>(&self) -> Option<&RefCell<T>> {
+        self.resources.get(&TypeId::of::<T>())?
+            .downcast_ref::<RefCell<T>>()
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&RefCell<SparseSet<T>>> {
+        self.storages.get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<RefCell<SparseSet<T>>>()
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut SparseSet<T> {
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RefCell::new(SparseSet::<T>::new())))
+            .as_any_mut()
+            .downcast_mut::<RefCell<SparseSet<T>>>()
+            .expect("Component storage has the wrong type")
+            .get_mut()
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
+}
+
+fn update_events<T: 'static>(world: &World) {
+    if let Some(mut events) = world.resource_mut::<Events<T>>() {
+        events.update();
+    }
+}
+
+fn snapshot_component<T: CustomComponent>(world: &World, entity: EntityId) -> Option<Component> {
+    world.get::<T>(entity).map(|c| Component::custom(c.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_move_between_entities() {
+        let mut world = World::new();
+        let first = world.spawn(vec![]);
+        let second = world.spawn(vec![]);
+
+        world.set_name(first, "player").unwrap();
+        world.set_name(second, "player").unwrap();
+
+        assert_eq!(world.find("player"), Some(second));
+        assert!(world.get::<Name>(first).is_none());
+        assert_eq!(world.get::<Name>(second).map(|n| n.0.clone()), Some("player".to_string()));
+    }
+
+    fn at(x: f32, y: f32) -> Component {
+        PositionComponent::new((x, y), (1.0, 1.0))
+    }
+
+    fn position(world: &World, entity: EntityId) -> (f32, f32) {
+        world.get::<PositionComponent>(entity).unwrap().position
+    }
+
+    #[test]
+    fn spawns_children_in_place() {
+        let mut world = World::new();
+        let mut arm = Entity::named("arm", vec![at(1.0, 0.0)]);
+        arm.children.push(Entity::named("hand", vec![at(0.5, 0.0)]));
+        let mut body = Entity::named("body", vec![at(10.0, 5.0)]);
+        body.children.push(arm);
+        world.spawn_entity(body);
+
+        assert_eq!(position(&world, world.find("arm").unwrap()), (11.0, 5.0));
+        assert_eq!(position(&world, world.find("hand").unwrap()), (11.5, 5.0));
+    }
+
+    #[test]
+    fn despawns_descendants_with_their_parent() {
+        let mut world = World::new();
+        let root = world.spawn(vec![]);
+        let child = world.spawn(vec![]);
+        let grandchild = world.spawn(vec![]);
+        world.set_parent(child, root).unwrap();
+        world.set_parent(grandchild, child).unwrap();
+
+        assert!(world.despawn(root));
+        assert!(!world.is_alive(child));
+        assert!(!world.is_alive(grandchild));
+        assert!(world.is_empty());
+    }
+
+    #[test]
+    fn despawning_a_child_detaches_it() {
+        let mut world = World::new();
+        let root = world.spawn(vec![]);
+        let child = world.spawn(vec![]);
+        world.set_parent(child, root).unwrap();
+
+        world.despawn(child);
+        assert!(world.is_alive(root));
+        assert!(world.children(root).is_empty());
+    }
+
+    #[test]
+    fn moves_children_between_parents() {
+        let mut world = World::new();
+        let first = world.spawn(vec![at(10.0, 0.0)]);
+        let second = world.spawn(vec![at(0.0, 20.0)]);
+        let child = world.spawn(vec![at(1.0, 1.0)]);
+        let grandchild = world.spawn(vec![at(1.0, 0.0)]);
+        world.set_parent(grandchild, child).unwrap();
+        world.set_parent(child, first).unwrap();
+
+        world.set_parent(child, second).unwrap();
+        assert_eq!(world.parent(child), Some(second));
+        assert!(world.children(first).is_empty());
+        assert_eq!(world.children(second), vec![child]);
+        // The child's position when it was attached is its new offset
+        assert_eq!(position(&world, child), (11.0, 21.0));
+        assert_eq!(position(&world, grandchild), (12.0, 21.0));
+
+        // Reparenting under a descendant would make a cycle
+        world.set_parent(second, grandchild).unwrap();
+        assert_eq!(world.parent(second), None);
+    }
+}
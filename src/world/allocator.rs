@@ -0,0 +1,153 @@
+use crate::world::EntityId;
+
+/// Hands out entity ids, reusing the slots of despawned entities.
+///
+/// Every reuse bumps the slot's generation, so a handle kept around after its
+/// entity was despawned no longer matches and is reported as dead. A slot
+/// whose generation has run out is retired rather than wrapped around, so an
+/// old handle can never match again.
+pub struct EntityAllocator {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    /// When each slot's current entity was allocated, counting up
+    spawned: Vec<u64>,
+    next_spawn: u64,
+    free: Vec<u32>,
+    retired: usize,
+}
+
+impl EntityAllocator {
+    pub fn new() -> Self {
+        EntityAllocator {
+            generations: vec![],
+            alive: vec![],
+            spawned: vec![],
+            next_spawn: 0,
+            free: vec![],
+            retired: 0,
+        }
+    }
+
+    pub fn allocate(&mut self) -> EntityId {
+        let spawned = self.next_spawn;
+        self.next_spawn += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                let slot = index as usize;
+                self.generations[slot] += 1;
+                self.alive[slot] = true;
+                self.spawned[slot] = spawned;
+                EntityId::new(index, self.generations[slot])
+            },
+            None => {
+                let index = self.generations.len() as u32;
+                self.generations.push(0);
+                self.alive.push(true);
+                self.spawned.push(spawned);
+                EntityId::new(index, 0)
+            },
+        }
+    }
+
+    /// Frees the entity's slot, returning false if the handle was already stale
+    pub fn deallocate(&mut self, entity: EntityId) -> bool {
+        if !self.is_alive(entity) { return false }
+
+        let slot = entity.index() as usize;
+        self.alive[slot] = false;
+        match self.generations[slot] {
+            u32::MAX => self.retired += 1,
+            _ => self.free.push(entity.index()),
+        }
+        true
+    }
+
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        let slot = entity.index() as usize;
+        match (self.alive.get(slot), self.generations.get(slot)) {
+            (Some(true), Some(generation)) => *generation == entity.generation(),
+            _ => false,
+        }
+    }
+
+    /// Orders entities by when they were allocated, unlike their ids, whose
+    /// slots are reused
+    pub fn spawn_order(&self, entity: EntityId) -> Option<u64> {
+        match self.is_alive(entity) {
+            true => Some(self.spawned[entity.index() as usize]),
+            false => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.generations.len() - self.free.len() - self.retired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.alive.iter()
+            .zip(self.generations.iter())
+            .enumerate()
+            .filter(|(_, (alive, _))| **alive)
+            .map(|(index, (_, generation))| EntityId::new(index as u32, *generation))
+    }
+}
+
+impl Default for EntityAllocator {
+    fn default() -> Self {
+        EntityAllocator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_stale_ids() {
+        let mut allocator = EntityAllocator::new();
+        let first = allocator.allocate();
+        assert!(allocator.deallocate(first));
+        assert!(!allocator.deallocate(first));
+
+        let second = allocator.allocate();
+        assert_eq!(second.index(), first.index());
+        assert!(allocator.is_alive(second));
+        assert!(!allocator.is_alive(first));
+        assert!(!allocator.deallocate(first));
+        assert_eq!(allocator.iter().collect::<Vec<_>>(), [second]);
+    }
+
+    #[test]
+    fn retires_slots_whose_generation_runs_out() {
+        let mut allocator = EntityAllocator::new();
+        let entity = allocator.allocate();
+        allocator.generations[0] = u32::MAX;
+        let last = EntityId::new(entity.index(), u32::MAX);
+
+        assert!(allocator.deallocate(last));
+        assert!(!allocator.is_alive(last));
+        assert_eq!(allocator.len(), 0);
+
+        let next = allocator.allocate();
+        assert_ne!(next.index(), entity.index());
+        assert_eq!(allocator.len(), 1);
+    }
+
+    #[test]
+    fn orders_reused_slots_after_older_entities() {
+        let mut allocator = EntityAllocator::new();
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+        allocator.deallocate(first);
+        let third = allocator.allocate();
+
+        assert_eq!(third.index(), first.index());
+        assert!(allocator.spawn_order(second) < allocator.spawn_order(third));
+        assert_eq!(allocator.spawn_order(first), None);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::world::{EntityId, World};
+use crate::world::storage::SparseSet;
+
+use std::cell::{Ref, RefMut};
+
+/// A set of component borrows that can be requested from a `World`.
+///
+/// Implemented for `&T`, `&mut T` and tuples of those, so that
+/// `world.query::<(&PositionComponent, &mut PhysicsComponent)>()` borrows the
+/// position storage immutably and the physics storage mutably.
+pub trait Fetch {
+    type Borrow<'w>;
+    type Item<'b>;
+
+    fn borrow(world: &World) -> Self::Borrow<'_>;
+    fn entities<'a>(borrow: &'a Self::Borrow<'_>) -> Option<&'a [EntityId]>;
+    fn contains(borrow: &Self::Borrow<'_>, entity: EntityId) -> bool;
+    fn fetch<'b>(borrow: &'b mut Self::Borrow<'_>, entity: EntityId) -> Option<Self::Item<'b>>;
+}
+
+impl<T: 'static> Fetch for &T {
+    type Borrow<'w> = Option<Ref<'w, SparseSet<T>>>;
+    type Item<'b> = &'b T;
+
+    fn borrow(world: &World) -> Self::Borrow<'_> {
+        world.borrow_storage::<T>()
+    }
+
+    fn entities<'a>(borrow: &'a Self::Borrow<'_>) -> Option<&'a [EntityId]> {
+        Some(borrow.as_ref().map_or(&[], |s| s.entities()))
+    }
+
+    fn contains(borrow: &Self::Borrow<'_>, entity: EntityId) -> bool {
+        borrow.as_ref().is_some_and(|s| s.contains(entity))
+    }
+
+    fn fetch<'b>(borrow: &'b mut Self::Borrow<'_>, entity: EntityId) -> Option<Self::Item<'b>> {
+        borrow.as_ref()?.get(entity)
+    }
+}
+
+impl<T: 'static> Fetch for &mut T {
+    type Borrow<'w> = Option<RefMut<'w, SparseSet<T>>>;
+    type Item<'b> = &'b mut T;
+
+    fn borrow(world: &World) -> Self::Borrow<'_> {
+        world.borrow_storage_mut::<T>()
+    }
+
+    fn entities<'a>(borrow: &'a Self::Borrow<'_>) -> Option<&'a [EntityId]> {
+        Some(borrow.as_ref().map_or(&[], |s| s.entities()))
+    }
+
+    fn contains(borrow: &Self::Borrow<'_>, entity: EntityId) -> bool {
+        borrow.as_ref().is_some_and(|s| s.contains(entity))
+    }
+
+    fn fetch<'b>(borrow: &'b mut Self::Borrow<'_>, entity: EntityId) -> Option<Self::Item<'b>> {
+        borrow.as_mut()?.get_mut(entity)
+    }
+}
+
+macro_rules! impl_fetch_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Fetch),+> Fetch for ($($name,)+) {
+            type Borrow<'w> = ($($name::Borrow<'w>,)+);
+            type Item<'b> = ($($name::Item<'b>,)+);
+
+            fn borrow(world: &World) -> Self::Borrow<'_> {
+                ($($name::borrow(world),)+)
+            }
+
+            // Iterate over whichever storage is smallest
+            fn entities<'a>(borrow: &'a Self::Borrow<'_>) -> Option<&'a [EntityId]> {
+                let ($($name,)+) = borrow;
+                [$($name::entities($name)),+]
+                    .into_iter()
+                    .flatten()
+                    .min_by_key(|e| e.len())
+            }
+
+            fn contains(borrow: &Self::Borrow<'_>, entity: EntityId) -> bool {
+                let ($($name,)+) = borrow;
+                $($name::contains($name, entity))&&+
+            }
+
+            fn fetch<'b>(borrow: &'b mut Self::Borrow<'_>, entity: EntityId) -> Option<Self::Item<'b>> {
+                let ($($name,)+) = borrow;
+                Some(($($name::fetch($name, entity)?,)+))
+            }
+        }
+    };
+}
+
+impl_fetch_tuple!(A);
+impl_fetch_tuple!(A, B);
+impl_fetch_tuple!(A, B, C);
+impl_fetch_tuple!(A, B, C, D);
+impl_fetch_tuple!(A, B, C, D, E);
+impl_fetch_tuple!(A, B, C, D, E, F);
+
+/// The storages borrowed by `World::query`, released when the query is dropped
+pub struct Query<'w, Q: Fetch> {
+    borrow: Q::Borrow<'w>,
+}
+
+impl<'w, Q: Fetch> Query<'w, Q> {
+    pub(crate) fn new(world: &'w World) -> Self {
+        Query {
+            borrow: Q::borrow(world),
+        }
+    }
+
+    /// Lists every entity that has all of the queried components
+    pub fn entities(&self) -> Vec<EntityId> {
+        match Q::entities(&self.borrow) {
+            Some(entities) => entities.iter()
+                .copied()
+                .filter(|e| Q::contains(&self.borrow, *e))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        Q::contains(&self.borrow, entity)
+    }
+
+    pub fn get(&mut self, entity: EntityId) -> Option<Q::Item<'_>> {
+        Q::fetch(&mut self.borrow, entity)
+    }
+
+    pub fn for_each<F>(&mut self, mut f: F)
+    where
+        F: FnMut(EntityId, Q::Item<'_>),
+    {
+        for entity in self.entities() {
+            if let Some(item) = Q::fetch(&mut self.borrow, entity) {
+                f(entity, item);
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::world::EntityId;
+
+pub struct SparseSet<T> {
+    sparse: Vec<Option<usize>>,
+    dense: Vec<EntityId>,
+    data: Vec<T>,
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        SparseSet {
+            sparse: vec![],
+            dense: vec![],
+            data: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn entities(&self) -> &[EntityId] {
+        &self.dense
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.index_of(entity).is_some()
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&T> {
+        self.index_of(entity).map(|i| &self.data[i])
+    }
+
+    pub fn get_mut(&mut self, entity: EntityId) -> Option<&mut T> {
+        self.index_of(entity).map(move |i| &mut self.data[i])
+    }
+
+    /// Borrows the values of two distinct entities mutably at the same time
+    pub fn get_pair_mut(&mut self, a: EntityId, b: EntityId) -> Option<(&mut T, &mut T)> {
+        let (i, j) = (self.index_of(a)?, self.index_of(b)?);
+        if i == j { return None }
+
+        if i < j {
+            let (low, high) = self.data.split_at_mut(j);
+            Some((&mut low[i], &mut high[0]))
+        } else {
+            let (low, high) = self.data.split_at_mut(i);
+            Some((&mut high[0], &mut low[j]))
+        }
+    }
+
+    /// Inserts a value for the entity, returning the value it replaced
+    pub fn insert(&mut self, entity: EntityId, value: T) -> Option<T> {
+        if let Some(i) = self.index_of(entity) {
+            return Some(std::mem::replace(&mut self.data[i], value));
+        }
+
+        let slot = entity.index() as usize;
+        if slot >= self.sparse.len() {
+            self.sparse.resize(slot + 1, None);
+        }
+
+        self.sparse[slot] = Some(self.dense.len());
+        self.dense.push(entity);
+        self.data.push(value);
+        None
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> Option<T> {
+        let i = self.index_of(entity)?;
+        self.sparse[entity.index() as usize] = None;
+
+        // Keep the dense arrays packed by moving the last element into the gap
+        self.dense.swap_remove(i);
+        let value = self.data.swap_remove(i);
+        if let Some(moved) = self.dense.get(i) {
+            self.sparse[moved.index() as usize] = Some(i);
+        }
+
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.dense.iter().copied().zip(self.data.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.dense.iter().copied().zip(self.data.iter_mut())
+    }
+
+    fn index_of(&self, entity: EntityId) -> Option<usize> {
+        let i = (*self.sparse.get(entity.index() as usize)?)?;
+        match self.dense[i] == entity {
+            true => Some(i),
+            false => None,
+        }
+    }
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        SparseSet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_replaces_existing_values() {
+        let mut set = SparseSet::new();
+        let entity = EntityId::new(3, 0);
+
+        assert_eq!(set.insert(entity, "a"), None);
+        assert_eq!(set.insert(entity, "b"), Some("a"));
+        assert_eq!(set.get(entity), Some(&"b"));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn remove_keeps_the_rest_reachable() {
+        let mut set = SparseSet::new();
+        let entities: Vec<EntityId> = (0..4).map(|i| EntityId::new(i, 0)).collect();
+        for (i, entity) in entities.iter().enumerate() {
+            set.insert(*entity, i);
+        }
+
+        assert_eq!(set.remove(entities[1]), Some(1));
+        assert_eq!(set.remove(entities[1]), None);
+        assert!(!set.contains(entities[1]));
+        for i in [0, 2, 3] {
+            assert_eq!(set.get(entities[i]), Some(&i));
+        }
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn ignores_other_generations_of_a_slot() {
+        let mut set = SparseSet::new();
+        set.insert(EntityId::new(0, 0), "old");
+
+        let reused = EntityId::new(0, 1);
+        assert!(!set.contains(reused));
+        assert_eq!(set.remove(reused), None);
+        assert_eq!(set.get(EntityId::new(0, 0)), Some(&"old"));
+    }
+}
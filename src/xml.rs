@@ -0,0 +1,335 @@
+use crate::atlas::AtlasError;
+use crate::components::custom::{self, ComponentParser, CustomComponent};
+use crate::entity::is_builtin_element;
+use crate::texture::TextureError;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// Settings for loading scenes from XML
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Reject elements and attributes the loader doesn't know instead of
+    /// skipping them
+    pub strict: bool,
+    components: HashMap<&'static str, ComponentParser>,
+}
+
+impl LoadOptions {
+    pub fn strict() -> Self {
+        LoadOptions {
+            strict: true,
+            ..LoadOptions::default()
+        }
+    }
+
+    /// Loads `<T::TAG>` elements under an `<entity>` as a `T`.
+    ///
+    /// Panics if `T::TAG` is one of the built-in elements, which would
+    /// always be loaded as the built-in component instead, or if a component
+    /// was already registered for it.
+    pub fn register<T: CustomComponent>(mut self) -> Self {
+        assert!(
+            !is_builtin_element(T::TAG),
+            "<{}> is a built-in element and can't be used by a custom component",
+            T::TAG,
+        );
+        assert!(
+            self.components.insert(T::TAG, custom::parse::<T>).is_none(),
+            "<{}> is already used by another custom component",
+            T::TAG,
+        );
+        self
+    }
+
+    /// Looks up the parser for a custom component's element
+    pub fn parser(&self, tag: &str) -> Option<ComponentParser> {
+        self.components.get(tag).copied()
+    }
+
+    pub fn unknown_element(&self, node: roxmltree::Node) -> Result<(), SceneError> {
+        match self.strict {
+            true => Err(SceneError::at_element(node, ErrorKind::UnknownElement)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn unknown_attribute(&self, node: roxmltree::Node, attribute: &roxmltree::Attribute) -> Result<(), SceneError> {
+        match self.strict {
+            true => Err(SceneError::at_attribute(node, attribute, ErrorKind::UnknownAttribute)),
+            false => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The document isn't well-formed XML
+    Syntax(roxmltree::Error),
+    UnknownElement,
+    UnknownAttribute,
+    MissingAttribute,
+    /// The attribute's value couldn't be parsed, or is out of range
+    InvalidValue(String),
+    /// The attribute can't be used along with the named one
+    Conflict(String),
+    /// The value has to be unique within the scene but is used twice
+    Duplicate(String),
+    /// A file the scene refers to couldn't be read
+    File(String, io::Error),
+    /// An image the scene refers to couldn't be decoded
+    Texture(String, TextureError),
+    /// An atlas the scene refers to couldn't be loaded
+    Atlas(String, AtlasError),
+}
+
+/// Describes what went wrong loading a scene and where
+#[derive(Debug)]
+pub struct SceneError {
+    pub kind: ErrorKind,
+    pub line: u32,
+    pub column: u32,
+    pub element: Option<String>,
+    pub attribute: Option<String>,
+}
+
+impl SceneError {
+    pub fn at_element(node: roxmltree::Node, kind: ErrorKind) -> Self {
+        let pos = node.document().text_pos_at(node.range().start);
+
+        SceneError {
+            kind,
+            line: pos.row,
+            column: pos.col,
+            element: Some(node.tag_name().name().to_string()),
+            attribute: None,
+        }
+    }
+
+    pub fn at_attribute(node: roxmltree::Node, attribute: &roxmltree::Attribute, kind: ErrorKind) -> Self {
+        let pos = node.document().text_pos_at(attribute.position());
+
+        SceneError {
+            kind,
+            line: pos.row,
+            column: pos.col,
+            element: Some(node.tag_name().name().to_string()),
+            attribute: Some(attribute.name().to_string()),
+        }
+    }
+
+    /// A file couldn't be read before any of it was parsed
+    pub fn file(path: &str, error: io::Error) -> Self {
+        SceneError {
+            kind: ErrorKind::File(path.to_string(), error),
+            line: 0,
+            column: 0,
+            element: None,
+            attribute: None,
+        }
+    }
+
+    pub fn missing_attribute(node: roxmltree::Node, attribute: &str) -> Self {
+        SceneError {
+            attribute: Some(attribute.to_string()),
+            ..SceneError::at_element(node, ErrorKind::MissingAttribute)
+        }
+    }
+}
+
+impl From<roxmltree::Error> for SceneError {
+    fn from(e: roxmltree::Error) -> Self {
+        let pos = e.pos();
+
+        SceneError {
+            kind: ErrorKind::Syntax(e),
+            line: pos.row,
+            column: pos.col,
+            element: None,
+            attribute: None,
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: ", self.line, self.column)?;
+        }
+
+        match (&self.element, &self.attribute) {
+            (Some(e), Some(a)) => write!(f, "<{} {}>: ", e, a)?,
+            (Some(e), None) => write!(f, "<{}>: ", e)?,
+            _ => (),
+        }
+
+        match &self.kind {
+            ErrorKind::Syntax(e) => write!(f, "{}", e),
+            ErrorKind::UnknownElement => write!(f, "Unknown element"),
+            ErrorKind::UnknownAttribute => write!(f, "Unknown attribute"),
+            ErrorKind::MissingAttribute => write!(f, "Missing attribute"),
+            ErrorKind::InvalidValue(v) => write!(f, "Invalid value \"{}\"", v),
+            ErrorKind::Conflict(other) => write!(f, "Cannot be used along with \"{}\"", other),
+            ErrorKind::Duplicate(v) => write!(f, "\"{}\" is already used", v),
+            ErrorKind::File(path, e) => write!(f, "Could not read {}: {}", path, e),
+            ErrorKind::Texture(path, e) => write!(f, "Could not decode {}: {}", path, e),
+            ErrorKind::Atlas(path, e) => write!(f, "Could not load atlas {}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Syntax(e) => Some(e),
+            ErrorKind::File(_, e) => Some(e),
+            ErrorKind::Texture(_, e) => Some(e),
+            ErrorKind::Atlas(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Parses an attribute's value, rejecting the infinities and NaNs that
+/// floats would otherwise accept
+pub fn parse_attribute<T: FromStr + 'static>(node: roxmltree::Node, attribute: &roxmltree::Attribute) -> Result<T, SceneError> {
+    let value = attribute.value()
+        .trim()
+        .parse::<T>()
+        .map_err(|_| invalid_value(node, attribute))?;
+
+    let any: &dyn Any = &value;
+    let finite = match (any.downcast_ref::<f32>(), any.downcast_ref::<f64>()) {
+        (Some(v), _) => v.is_finite(),
+        (_, Some(v)) => v.is_finite(),
+        _ => true,
+    };
+    match finite {
+        true => Ok(value),
+        false => Err(invalid_value(node, attribute)),
+    }
+}
+
+/// Parses an angle written in degrees as radians
+pub fn parse_degrees(node: roxmltree::Node, attribute: &roxmltree::Attribute) -> Result<f32, SceneError> {
+    parse_attribute::<f64>(node, attribute).map(|d| d.to_radians() as f32)
+}
+
+/// Writes an angle in radians as degrees, with the fewest decimals that
+/// `parse_degrees` reads back as exactly the same angle
+pub fn write_degrees(radians: f32) -> String {
+    let degrees = (radians as f64).to_degrees();
+    (0..17)
+        .map(|decimals| format!("{:.*}", decimals, degrees))
+        .find(|d| d.parse::<f64>().map(|d| d.to_radians() as f32) == Ok(radians))
+        .unwrap_or_else(|| degrees.to_string())
+}
+
+pub fn invalid_value(node: roxmltree::Node, attribute: &roxmltree::Attribute) -> SceneError {
+    SceneError::at_attribute(node, attribute, ErrorKind::InvalidValue(attribute.value().to_string()))
+}
+
+/// Formats an element with its attributes, nesting the already formatted
+/// children one level deeper
+pub fn write_element(tag: &str, attributes: &[(&str, String)], children: &[String]) -> String {
+    let mut out = format!("<{}", tag);
+    for (name, value) in attributes.iter() {
+        out += &format!(" {}=\"{}\"", name, escape(value));
+    }
+
+    if children.is_empty() {
+        out += "/>\n";
+        return out;
+    }
+
+    out += ">\n";
+    for child in children.iter() {
+        for line in child.lines() {
+            out += &format!("    {}\n", line);
+        }
+    }
+    out += &format!("</{}>\n", tag);
+
+    out
+}
+
+pub fn escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_degrees(value: &str) -> f32 {
+        let xml = format!("<position rotation=\"{}\"/>", value);
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let node = document.root_element();
+        parse_degrees(node, &node.attributes().next().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn degrees_round_trip_exactly() {
+        for bits in (0..0x7f80_0000u32).step_by(0x1_0001) {
+            let radians = f32::from_bits(bits);
+            assert_eq!(read_degrees(&write_degrees(radians)), radians);
+            assert_eq!(read_degrees(&write_degrees(-radians)), -radians);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "<sprite> is a built-in element")]
+    fn custom_components_cannot_use_built_in_elements() {
+        #[derive(Clone, PartialEq)]
+        struct Sprite;
+
+        impl CustomComponent for Sprite {
+            const TAG: &'static str = "sprite";
+
+            fn from_xml(_: roxmltree::Node, _: &LoadOptions) -> Result<Self, SceneError> {
+                Ok(Sprite)
+            }
+
+            fn to_xml(&self) -> String {
+                write_element(Self::TAG, &[], &[])
+            }
+        }
+
+        let _ = LoadOptions::default().register::<Sprite>();
+    }
+
+    #[test]
+    #[should_panic(expected = "<hat> is already used")]
+    fn custom_components_cannot_share_an_element() {
+        #[derive(Clone, PartialEq)]
+        struct Hat;
+
+        impl CustomComponent for Hat {
+            const TAG: &'static str = "hat";
+
+            fn from_xml(_: roxmltree::Node, _: &LoadOptions) -> Result<Self, SceneError> {
+                Ok(Hat)
+            }
+
+            fn to_xml(&self) -> String {
+                write_element(Self::TAG, &[], &[])
+            }
+        }
+
+        let _ = LoadOptions::default().register::<Hat>().register::<Hat>();
+    }
+
+    #[test]
+    fn degrees_are_written_briefly() {
+        assert_eq!(write_degrees(read_degrees("90")), "90");
+        assert_eq!(write_degrees(read_degrees("-12.5")), "-12.5");
+        assert_eq!(write_degrees(0.0), "0");
+    }
+}
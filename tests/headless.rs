@@ -0,0 +1,89 @@
+use bastel::components::animation::AnimationComponent;
+use bastel::components::physics::PhysicsComponent;
+use bastel::components::position::PositionComponent;
+use bastel::engine::Engine;
+use bastel::events::{AnimationFinished, EventReader, Key, KeyEvent};
+use bastel::scene::Scene;
+use bastel::time::Time;
+
+fn engine(xml: &str) -> Engine {
+    let scene = Scene::from_xml(xml).unwrap();
+    Engine::builder("test", 320, 240)
+        .scene(scene)
+        .build_headless()
+        .unwrap()
+}
+
+fn position(engine: &Engine, name: &str) -> (f32, f32) {
+    let world = engine.world().unwrap();
+    let entity = world.find(name).unwrap();
+    let position = world.get::<PositionComponent>(entity).unwrap().position;
+    position
+}
+
+#[test]
+fn runs_fixed_steps() {
+    let mut engine = engine(r#"<scene>
+        <entity id="ball"><position x="0" y="0" width="1" height="1"/><physics vx="0.5"/></entity>
+    </scene>"#);
+
+    engine.run_frames(60);
+
+    let world = engine.world().unwrap();
+    assert_eq!(world.resource::<Time>().unwrap().frame_count, 60);
+    let ball = world.find("ball").unwrap();
+    assert_eq!(world.get::<PhysicsComponent>(ball).unwrap().velocity, (0.5, 0.0));
+    assert!((position(&engine, "ball").0 - 30.0).abs() < 1e-3);
+}
+
+#[test]
+fn moves_entities_from_key_events() {
+    let mut engine = engine(r#"<scene>
+        <entity id="player"><position x="0.5" y="0.5" width="0.1" height="0.1"/><physics/><moveable/></entity>
+    </scene>"#);
+
+    engine.world().unwrap().send_event(KeyEvent { key: Key::Right, pressed: true });
+    engine.run_frames(10);
+
+    let (x, y) = position(&engine, "player");
+    assert!(x > 0.5);
+    assert_eq!(y, 0.5);
+}
+
+#[test]
+fn plays_animations_to_the_end() {
+    let mut engine = engine(r#"<scene>
+        <entity id="door">
+            <position x="0" y="0" width="16" height="16"/>
+            <sprite/>
+            <animation>
+                <clip name="open" mode="once" duration="0.05"><frame cell="0"/><frame cell="1"/></clip>
+            </animation>
+        </entity>
+    </scene>"#);
+    let mut reader = EventReader::<AnimationFinished>::new();
+
+    let mut finished = vec![];
+    for _ in 0..20 {
+        engine.run_frames(1);
+        let world = engine.world().unwrap();
+        finished.extend(reader.read(&world.events::<AnimationFinished>()).map(|e| e.clip.clone()));
+    }
+
+    assert_eq!(finished, ["open"]);
+    let world = engine.world().unwrap();
+    let door = world.find("door").unwrap();
+    assert!(world.get::<AnimationComponent>(door).unwrap().is_finished());
+}
+
+#[test]
+fn saves_a_running_scene_as_it_is() {
+    let mut engine = engine(r#"<scene>
+        <entity id="ball"><position x="0" y="0" width="1" height="1"/><physics vx="0.25" vy="-0.5"/></entity>
+    </scene>"#);
+
+    engine.run_frames(7);
+
+    let saved = engine.scenes.active().unwrap().to_xml();
+    assert_eq!(Scene::from_xml(&saved).unwrap().to_xml(), saved);
+}